@@ -0,0 +1,141 @@
+use std::{collections::HashMap, fmt::Display};
+
+use serde::Serialize;
+
+use crate::{anonymize::Anonymizer, hashing::DisplayHash};
+
+/// Number of entries of each list shown in the text report.
+const TOP: usize = 10;
+
+/// Health of a map: people learned from several images. Faces close in
+/// Hamming distance aren't reported, XXH3 keys of similar images being no
+/// closer than those of any two images.
+#[derive(Serialize, Debug)]
+pub struct MapAnalysis {
+    pub faces: usize,
+    pub names: usize,
+    /// Names mapped from several hashes, the most mapped ones first
+    pub repeated_names: Vec<(String, usize)>,
+}
+
+impl MapAnalysis {
+    pub fn anonymized(mut self, anonymizer: &Anonymizer) -> Self {
+        for (name, _) in &mut self.repeated_names {
+            *name = anonymizer.alias(name);
+        }
+        self
+    }
+}
+
+pub fn analyze(hash_map: &HashMap<u64, String>) -> MapAnalysis {
+    let mut counts: HashMap<&String, usize> = HashMap::new();
+    for name in hash_map.values() {
+        *counts.entry(name).or_default() += 1;
+    }
+    let names = counts.len();
+    let mut repeated_names: Vec<(String, usize)> = counts
+        .into_iter()
+        .filter(|&(_, count)| count > 1)
+        .map(|(name, count)| (name.clone(), count))
+        .collect();
+    repeated_names.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    MapAnalysis {
+        faces: hash_map.len(),
+        names,
+        repeated_names,
+    }
+}
+
+impl Display for MapAnalysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} faces of {} people", self.faces, self.names)?;
+        write!(f, "{} people have several faces", self.repeated_names.len())?;
+        for (name, count) in self.repeated_names.iter().take(TOP) {
+            write!(f, "\n  {:<32} {:>4}", name, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// A face mapped to different names by two maps.
+#[derive(Serialize, Debug)]
+pub struct ChangedFace {
+    pub hash: u64,
+    pub names: (String, String),
+}
+
+/// Differences between two maps, each list sorted by hash.
+#[derive(Serialize, Debug)]
+pub struct MapDiff {
+    pub only_a: Vec<(u64, String)>,
+    pub only_b: Vec<(u64, String)>,
+    pub changed: Vec<ChangedFace>,
+}
+
+impl MapDiff {
+    pub fn anonymized(mut self, anonymizer: &Anonymizer) -> Self {
+        for (_, name) in self.only_a.iter_mut().chain(&mut self.only_b) {
+            *name = anonymizer.alias(name);
+        }
+        for face in &mut self.changed {
+            face.names = (
+                anonymizer.alias(&face.names.0),
+                anonymizer.alias(&face.names.1),
+            );
+        }
+        self
+    }
+}
+
+pub fn diff(a: &HashMap<u64, String>, b: &HashMap<u64, String>) -> MapDiff {
+    let only = |a: &HashMap<u64, String>, b: &HashMap<u64, String>| {
+        let mut faces: Vec<(u64, String)> = a
+            .iter()
+            .filter(|(hash, _)| !b.contains_key(hash))
+            .map(|(&hash, name)| (hash, name.clone()))
+            .collect();
+        faces.sort_unstable();
+        faces
+    };
+
+    let mut changed: Vec<ChangedFace> = a
+        .iter()
+        .filter_map(|(&hash, name_a)| {
+            let name_b = b.get(&hash).filter(|name_b| *name_b != name_a)?;
+            Some(ChangedFace {
+                hash,
+                names: (name_a.clone(), name_b.clone()),
+            })
+        })
+        .collect();
+    changed.sort_by_key(|c| c.hash);
+
+    MapDiff {
+        only_a: only(a, b),
+        only_b: only(b, a),
+        changed,
+    }
+}
+
+impl Display for MapDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (label, faces) in [("A", &self.only_a), ("B", &self.only_b)] {
+            writeln!(f, "{} faces only in {}", faces.len(), label)?;
+            for (hash, name) in faces.iter().take(TOP) {
+                writeln!(f, "  {} ({})", DisplayHash(*hash), name)?;
+            }
+        }
+        write!(f, "{} faces with different names", self.changed.len())?;
+        for face in self.changed.iter().take(TOP) {
+            write!(
+                f,
+                "\n  {}: {} -> {}",
+                DisplayHash(face.hash),
+                face.names.0,
+                face.names.1
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,19 @@
+use ahash::RandomState;
+
+/// Replaces names with hashes salted once per run: a person keeps the same
+/// alias within a report, but the alias can't be traced back to them.
+pub struct Anonymizer {
+    salt: RandomState,
+}
+
+impl Anonymizer {
+    pub fn new() -> Self {
+        Self {
+            salt: RandomState::new(),
+        }
+    }
+
+    pub fn alias(&self, name: &str) -> String {
+        format!("person-{:08x}", self.salt.hash_one(name) as u32)
+    }
+}
@@ -0,0 +1,394 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+
+use crate::{
+    encoding::BodyEncoding,
+    evaluate::Strategies,
+    hashing::{parse_hash, HashStrategy},
+    human_delay::HumanDelay,
+    names::{KeyBy, NameFormat},
+    strategy::{LearningStrategy, UnknownStrategy},
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Args {
+    /// TOML configuration file, overridden by environment variables and flags
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Print the resolved configuration, the password redacted, and exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// Base URL of the Lucca instance
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// Email used to log in
+    #[arg(long)]
+    pub email: Option<String>,
+
+    /// Play training games
+    #[arg(long, overrides_with = "no_training")]
+    pub training: bool,
+
+    /// Play ranked games, even when the configuration sets training
+    #[arg(long)]
+    pub no_training: bool,
+
+    /// File storing the learned faces
+    #[arg(long, value_name = "PATH")]
+    pub data: Option<PathBuf>,
+
+    /// File of answers pinned by hand, consulted before the learned faces
+    #[arg(long, value_name = "PATH")]
+    pub overrides: Option<PathBuf>,
+
+    /// PEM certificate of a private CA to trust when connecting to Lucca
+    #[arg(long, value_name = "PATH")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// CSS selector of the verification token of the login form, for login
+    /// pages whose markup differs
+    #[arg(long, value_name = "SELECTOR")]
+    pub token_selector: Option<String>,
+
+    /// File remembering the hash of each image URL, so that faces seen in
+    /// previous runs aren't downloaded again
+    #[arg(long, value_name = "PATH")]
+    pub url_cache: Option<PathBuf>,
+
+    /// Age after which a cached URL is downloaded again
+    #[arg(long, value_name = "HOURS")]
+    pub url_cache_ttl_hours: Option<u64>,
+
+    /// Share of correct answers under which the maximize-score strategy asks
+    /// about a known face in interactive mode, see --stats
+    #[arg(long, value_name = "RATIO")]
+    pub min_confidence: Option<f64>,
+
+    /// Number of times a face must be answered with the same name before it
+    /// is answered with it, to keep one-off errors out of the map
+    #[arg(long, value_name = "N")]
+    pub learn_threshold: Option<u32>,
+
+    /// Save the faces awaiting confirmation too, rather than only those
+    /// reaching --learn-threshold within the run
+    #[arg(long)]
+    pub keep_pending: bool,
+
+    /// Time after which an image download is given up on, in milliseconds, the question being
+    /// answered as an unknown face
+    #[arg(long, value_name = "MS")]
+    pub image_timeout_ms: Option<u64>,
+
+    /// Size under which an image is taken for a placeholder, its face being
+    /// answered as unknown rather than learned
+    #[arg(long, value_name = "BYTES")]
+    pub min_image_bytes: Option<u64>,
+
+    /// Wait a random think time within MIN-MAX milliseconds before each answer
+    #[arg(long, value_name = "MIN-MAX")]
+    pub human_delay: Option<HumanDelay>,
+
+    /// Number of times a game failing midway is abandoned for a new one, so
+    /// that unattended runs still complete a game
+    #[arg(long, value_name = "N")]
+    pub max_game_retries: Option<usize>,
+
+    /// Seed of the random think times, for reproducible runs
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Encoding of the bodies of the game requests, for Lucca versions not
+    /// expecting JSON
+    #[arg(long, value_enum)]
+    pub encoding: Option<BodyEncoding>,
+
+    /// File keeping every sighting of each face, for the learning curve
+    #[arg(long, value_name = "PATH")]
+    pub stats: Option<PathBuf>,
+
+    /// Fail with a dedicated exit code when the share of correct answers of a
+    /// ranked game is below this, to detect a map gone stale
+    #[arg(long, value_name = "RATIO")]
+    pub min_accuracy: Option<f64>,
+
+    /// Report the sizes of the images downloaded, to tune how much of them
+    /// is hashed
+    #[arg(long)]
+    pub image_sizes: bool,
+
+    /// Play canned games without a Lucca instance, to try the whole flow,
+    /// without saving anything. Also enabled by setting MOCK to anything but
+    /// 0, false or no
+    #[arg(long)]
+    pub mock: bool,
+
+    /// Play this many games against the simulated server, without saving nor
+    /// waiting between answers, and report the questions answered per
+    /// second: the cost of our own code, without the network's
+    #[arg(long, value_name = "GAMES", conflicts_with = "interactive")]
+    pub bench: Option<u32>,
+
+    /// Play this many games in a row, each one being reported and saved once
+    /// over. Several games end with a summary of them all, written to
+    /// --summary-out when given
+    #[arg(long, value_name = "GAMES", conflicts_with = "bench")]
+    pub games: Option<u32>,
+
+    /// Play this many games before the measured ones, learning from them
+    /// without reporting them, so that the measures reflect a warmed up map
+    /// and URL cache
+    #[arg(long, value_name = "GAMES", conflicts_with = "bench")]
+    pub warmup_games: Option<u32>,
+
+    /// Stop playing --games once a training game shows no new face, there
+    /// being nothing left to learn
+    #[arg(long, requires = "games")]
+    pub stop_when_fully_known: bool,
+
+    /// Don't keep the cookies between requests, for gateways that
+    /// authenticate the requests themselves. The login form relies on its
+    /// cookies: without them, the game requests aren't logged in
+    #[arg(long, overrides_with = "cookie_store")]
+    pub no_cookie_store: bool,
+
+    /// Keep the cookies between requests, even when the configuration says
+    /// not to
+    #[arg(long)]
+    pub cookie_store: bool,
+
+    /// Log in for the session only, rather than for a long-lived cookie,
+    /// which is safer on a shared machine
+    #[arg(long, overrides_with = "persistent_login")]
+    pub session_login: bool,
+
+    /// Log in for a long-lived cookie, even when the configuration asks for
+    /// a session login
+    #[arg(long)]
+    pub persistent_login: bool,
+
+    /// Log the bodies of the requests and responses, with RUST_LOG=trace,
+    /// passwords and tokens redacted. They hold the names of colleagues, so
+    /// the logs shouldn't be shared
+    #[arg(long)]
+    pub trace_bodies: bool,
+
+    /// Send the counters of the game to this statsd server, as HOST:PORT,
+    /// with the statsd feature
+    #[arg(long, value_name = "ADDRESS")]
+    pub statsd: Option<String>,
+
+    /// End the Lucca session once the game is over, for shared machines
+    #[arg(long)]
+    pub logout: bool,
+
+    /// Only log in, to check the credentials without playing a game
+    #[arg(long, conflicts_with_all = ["accuracy_only", "bench"])]
+    pub dry_login: bool,
+
+    /// File listing the names to train on, one per line. Only their faces
+    /// are learned, and the summary reports how many are known
+    #[arg(long, value_name = "PATH")]
+    pub targets: Option<PathBuf>,
+
+    /// Learn during the run but never write the data file
+    #[arg(long)]
+    pub no_save: bool,
+
+    /// Show unknown faces and ask who they are, falls back to the strategies
+    /// when not run in a terminal
+    #[arg(long)]
+    pub interactive: bool,
+
+    /// Number of previous versions of the data file kept on save, as `data.1`, `data.2`...
+    #[arg(long, value_name = "N")]
+    pub backups: Option<usize>,
+
+    /// Restore the N-th most recent backup of the data file
+    #[arg(long, value_name = "N")]
+    pub restore_backup: Option<usize>,
+
+    /// Gzip the data file, also done when its extension is `.gz`
+    #[arg(long, overrides_with = "no_compress")]
+    pub compress: bool,
+
+    /// Don't gzip the data file unless its extension is `.gz`, even when the
+    /// configuration says to
+    #[arg(long)]
+    pub no_compress: bool,
+
+    /// Write the keys of the data file as hexadecimal, like the debug logs
+    #[arg(long, overrides_with = "no_hex_keys")]
+    pub hex_keys: bool,
+
+    /// Write the keys of the data file in decimal, even when the
+    /// configuration says hexadecimal
+    #[arg(long)]
+    pub no_hex_keys: bool,
+
+    /// Evaluate the learned map against a directory of images labeled in `labels.ron`
+    #[arg(long, value_name = "DIR")]
+    pub evaluate: Option<PathBuf>,
+
+    /// Write the evaluation as JSON to this file
+    #[arg(long, value_name = "PATH", requires = "evaluate")]
+    pub report: Option<PathBuf>,
+
+    /// Compare combinations of strategies, written `<hash>:<unknown>`, on the evaluated images
+    #[arg(long, value_name = "STRATEGIES", requires = "evaluate")]
+    pub compare: Vec<Strategies>,
+
+    /// Make flashcards of a directory of images labeled in `labels.ron`: a
+    /// single HTML page, written to --deck-out, whose names show on click
+    #[arg(long, value_name = "DIR", requires = "deck_out")]
+    pub deck: Option<PathBuf>,
+
+    /// File the page of --deck is written to
+    #[arg(long, value_name = "PATH", requires = "deck")]
+    pub deck_out: Option<PathBuf>,
+
+    /// Rekey the data file to the configured hash strategy, using a directory of images labeled
+    /// in `labels.ron`; faces none of them show are dropped
+    #[arg(long, value_name = "DIR")]
+    pub convert_strategy: Option<PathBuf>,
+
+    /// Map the face stored under HASH to NAME and save the map. Hashes are
+    /// given as displayed, in hexadecimal prefixed with 0x, or in decimal
+    #[arg(long, num_args = 2, value_names = ["HASH", "NAME"])]
+    pub fix: Vec<String>,
+
+    /// Forget the face stored under HASH and save the map
+    #[arg(long, value_name = "HASH", value_parser = parse_hash)]
+    pub remove: Option<u64>,
+
+    /// Forget every face stored under NAME, compared according to --name-format, and save the map
+    #[arg(long, value_name = "NAME")]
+    pub forget: Option<String>,
+
+    /// Print the stored face closest to HASH
+    #[arg(long, value_name = "HASH", value_parser = parse_hash)]
+    pub nearest: Option<u64>,
+
+    /// Report the people with several faces
+    #[arg(long)]
+    pub analyze: bool,
+
+    /// Report the faces only in data file A, only in B, and mapped to different names
+    #[arg(long, num_args = 2, value_names = ["A", "B"])]
+    pub diff: Vec<PathBuf>,
+
+    /// Report how many sightings faces need before being answered reliably,
+    /// from the stats file
+    #[arg(long)]
+    pub learning_curve: bool,
+
+    /// Play a training game without learning, and report how often the map names the faces
+    /// it knows correctly
+    #[arg(long)]
+    pub accuracy_only: bool,
+
+    /// Report the N faces most often answered wrongly, from the stats file
+    #[arg(long, value_name = "N")]
+    pub top_missed: Option<usize>,
+
+    /// Estimate how many of QUESTIONS questions the map would answer
+    /// correctly, from the stats file, to tell whether to train more first
+    #[arg(long, value_name = "QUESTIONS")]
+    pub estimate: Option<u32>,
+
+    /// Report how many people the map can name
+    #[arg(long)]
+    pub coverage: bool,
+
+    /// Number of people in the directory, for --coverage to report a share
+    #[arg(long, value_name = "N", requires = "coverage")]
+    pub directory_size: Option<usize>,
+
+    /// Sightings under which --top-missed leaves a face out, as its ratio would be noise
+    #[arg(long, value_name = "N", default_value_t = 3, requires = "top_missed")]
+    pub min_seen: usize,
+
+    /// Maximum Hamming distance, in bits, accepted by --nearest (64 by
+    /// default)
+    #[arg(long, value_name = "BITS")]
+    pub max_distance: Option<u32>,
+
+    /// Replace names with aliases in the summary, the confusion matrix and
+    /// the reports, the same person keeping the same alias within a run
+    #[arg(long)]
+    pub anonymize: bool,
+
+    /// Format of the game summary and the reports
+    #[arg(long, value_enum, default_value_t)]
+    pub format: Format,
+
+    /// Write the game summary to this file instead of stdout. With --games,
+    /// each game gets its own file, numbered like `summary-2.json`, and this
+    /// one gets the summary of them all
+    #[arg(long, value_name = "PATH")]
+    pub summary_out: Option<PathBuf>,
+
+    /// Write the confusion matrix of the game to this file, as CSV: a row
+    /// per correct name, a column per name answered. With --games, each game
+    /// gets its own file, numbered like --summary-out
+    #[arg(long, value_name = "PATH")]
+    pub confusion_out: Option<PathBuf>,
+
+    /// Write the confusion matrix as `correct,guessed,count` lines, only for
+    /// the non-zero cells
+    #[arg(long, requires = "confusion_out")]
+    pub confusion_long: bool,
+
+    /// Play a ranked game even though no face has been learned yet
+    #[arg(long)]
+    pub allow_empty: bool,
+
+    /// How to answer questions, `maximize-coverage` requires training mode
+    #[arg(long, value_enum)]
+    pub strategy: Option<LearningStrategy>,
+
+    /// With `maximize-coverage`, answer known faces without learning them again
+    #[arg(long, overrides_with = "no_skip_known")]
+    pub skip_known: bool,
+
+    /// Learn known faces again, even when the configuration skips them
+    #[arg(long)]
+    pub no_skip_known: bool,
+
+    /// How to answer faces that aren't in the map
+    #[arg(long, value_enum)]
+    pub unknown_strategy: Option<UnknownStrategy>,
+
+    /// Suggestion declining to answer, as "Je ne sais pas" in some games,
+    /// preferred over a blind guess for faces that aren't in the map
+    #[arg(long, value_name = "NAME")]
+    pub fallback_value: Option<String>,
+
+    /// How loosely learned names are compared to suggestions
+    #[arg(long, value_enum)]
+    pub name_format: Option<NameFormat>,
+
+    /// What faces are mapped to, the displayed name or the suggestion id.
+    /// Maps and overrides built with one can't be used with the other
+    #[arg(long, value_enum)]
+    pub key_by: Option<KeyBy>,
+
+    /// What part of the images is hashed, changing it invalidates the map
+    #[arg(long, value_enum)]
+    pub hash_strategy: Option<HashStrategy>,
+
+    /// Print how long each phase of the run took
+    #[arg(long)]
+    pub profile: bool,
+}
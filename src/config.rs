@@ -0,0 +1,482 @@
+use std::{fs::read_to_string, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{
+    cli::Args,
+    encoding::BodyEncoding,
+    hashing::HashStrategy,
+    human_delay::HumanDelay,
+    names::{KeyBy, NameFormat},
+    player::{
+        DEFAULT_MIN_CONFIDENCE, DEFAULT_TOKEN_SELECTOR, DEFAULT_URL_CACHE_TTL_HOURS, HASH_FILE_PATH,
+    },
+    strategy::{LearningStrategy, UnknownStrategy},
+};
+
+/// One source of configuration, unset values fall through to the sources
+/// with a lower precedence.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigLayer {
+    lucca_url: Option<String>,
+    email: Option<String>,
+    password: Option<String>,
+    training: Option<bool>,
+    data_path: Option<PathBuf>,
+    strategy: Option<LearningStrategy>,
+    skip_known: Option<bool>,
+    unknown_strategy: Option<UnknownStrategy>,
+    hash_strategy: Option<HashStrategy>,
+    compress: Option<bool>,
+    overrides_path: Option<PathBuf>,
+    name_format: Option<NameFormat>,
+    backups: Option<usize>,
+    key_by: Option<KeyBy>,
+    ca_cert: Option<PathBuf>,
+    url_cache_path: Option<PathBuf>,
+    url_cache_ttl_hours: Option<u64>,
+    encoding: Option<BodyEncoding>,
+    stats_path: Option<PathBuf>,
+    targets_path: Option<PathBuf>,
+    min_confidence: Option<f64>,
+    hex_keys: Option<bool>,
+    image_timeout_ms: Option<u64>,
+    human_delay: Option<HumanDelay>,
+    seed: Option<u64>,
+    max_game_retries: Option<usize>,
+    fallback_value: Option<String>,
+    learn_threshold: Option<u32>,
+    min_image_bytes: Option<u64>,
+    token_selector: Option<String>,
+    statsd: Option<String>,
+    cookie_store: Option<bool>,
+    persistent_login: Option<bool>,
+}
+
+/// The resolved configuration. It serializes as a configuration file, with
+/// the password redacted so that it can be shared.
+#[derive(Serialize, Debug)]
+pub struct Config {
+    pub lucca_url: Option<String>,
+    pub email: Option<String>,
+    #[serde(serialize_with = "redact")]
+    pub password: Option<String>,
+    pub training: bool,
+    pub data_path: PathBuf,
+    pub strategy: LearningStrategy,
+    pub skip_known: bool,
+    pub unknown_strategy: UnknownStrategy,
+    pub hash_strategy: HashStrategy,
+    pub compress: bool,
+    pub overrides_path: Option<PathBuf>,
+    pub name_format: NameFormat,
+    pub backups: usize,
+    pub key_by: KeyBy,
+    pub ca_cert: Option<PathBuf>,
+    pub url_cache_path: Option<PathBuf>,
+    pub url_cache_ttl_hours: u64,
+    pub encoding: BodyEncoding,
+    pub stats_path: Option<PathBuf>,
+    pub targets_path: Option<PathBuf>,
+    pub min_confidence: f64,
+    pub hex_keys: bool,
+    pub image_timeout_ms: Option<u64>,
+    pub human_delay: Option<HumanDelay>,
+    pub seed: Option<u64>,
+    pub max_game_retries: usize,
+    pub fallback_value: Option<String>,
+    pub learn_threshold: u32,
+    pub min_image_bytes: u64,
+    pub token_selector: String,
+    pub statsd: Option<String>,
+    pub cookie_store: bool,
+    pub persistent_login: bool,
+}
+
+fn redact<S: Serializer>(password: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match password {
+        Some(_) => serializer.serialize_str("<redacted>"),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// Reads a boolean variable: `1`, `true`, `yes` and `on` set the flag, `0`,
+/// `false`, `no`, `off` and an empty value clear it.
+fn env_flag(name: &str) -> Result<Option<bool>> {
+    env_var(name)
+        .map(|value| parse_flag(name, &value))
+        .transpose()
+}
+
+fn parse_flag(name: &str, value: &str) -> Result<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "" | "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(anyhow!(
+            "Invalid {}: {}, expected true or false",
+            name,
+            value
+        )),
+    }
+}
+
+/// A flag given on the command line, `set` for its positive form and `unset`
+/// for its `--no-*` one, the last of which wins.
+fn cli_flag(set: bool, unset: bool) -> Option<bool> {
+    match (set, unset) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses an enum variable the way its command line option is, listing the
+/// accepted values when it is invalid.
+fn env_enum<T: ValueEnum>(name: &str) -> Result<Option<T>> {
+    let Some(value) = env_var(name) else {
+        return Ok(None);
+    };
+    T::from_str(&value, true).map(Some).map_err(|_| {
+        let values: Vec<String> = T::value_variants()
+            .iter()
+            .filter_map(|v| v.to_possible_value())
+            .map(|v| v.get_name().to_owned())
+            .collect();
+        anyhow!(
+            "Invalid {}: {}, expected one of {}",
+            name,
+            value,
+            values.join(", ")
+        )
+    })
+}
+
+impl ConfigLayer {
+    fn from_file(path: &PathBuf) -> Result<Self> {
+        let content = read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read the config file {}: {}", path.display(), e))?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    fn from_env() -> Result<Self> {
+        let strategy = env_enum("LUCCA_STRATEGY")?;
+        let unknown_strategy = env_enum("LUCCA_UNKNOWN_STRATEGY")?;
+        let hash_strategy = env_enum("LUCCA_HASH_STRATEGY")?;
+        let backups = env_var("LUCCA_BACKUPS")
+            .map(|b| b.parse())
+            .transpose()
+            .map_err(|e| anyhow!("Invalid LUCCA_BACKUPS: {}", e))?;
+        let encoding = env_enum("LUCCA_ENCODING")?;
+        let min_confidence = env_var("LUCCA_MIN_CONFIDENCE")
+            .map(|c| c.parse())
+            .transpose()
+            .map_err(|e| anyhow!("Invalid LUCCA_MIN_CONFIDENCE: {}", e))?;
+        let image_timeout_ms = env_var("LUCCA_IMAGE_TIMEOUT_MS")
+            .map(|t| t.parse())
+            .transpose()
+            .map_err(|e| anyhow!("Invalid LUCCA_IMAGE_TIMEOUT_MS: {}", e))?;
+        let human_delay = env_var("LUCCA_HUMAN_DELAY")
+            .map(|d| d.parse())
+            .transpose()
+            .map_err(|e| anyhow!("Invalid LUCCA_HUMAN_DELAY: {}", e))?;
+        let seed = env_var("LUCCA_SEED")
+            .map(|s| s.parse())
+            .transpose()
+            .map_err(|e| anyhow!("Invalid LUCCA_SEED: {}", e))?;
+        let max_game_retries = env_var("LUCCA_MAX_GAME_RETRIES")
+            .map(|r| r.parse())
+            .transpose()
+            .map_err(|e| anyhow!("Invalid LUCCA_MAX_GAME_RETRIES: {}", e))?;
+        let learn_threshold = env_var("LUCCA_LEARN_THRESHOLD")
+            .map(|t| t.parse())
+            .transpose()
+            .map_err(|e| anyhow!("Invalid LUCCA_LEARN_THRESHOLD: {}", e))?;
+        let min_image_bytes = env_var("LUCCA_MIN_IMAGE_BYTES")
+            .map(|b| b.parse())
+            .transpose()
+            .map_err(|e| anyhow!("Invalid LUCCA_MIN_IMAGE_BYTES: {}", e))?;
+        let url_cache_ttl_hours = env_var("LUCCA_URL_CACHE_TTL_HOURS")
+            .map(|t| t.parse())
+            .transpose()
+            .map_err(|e| anyhow!("Invalid LUCCA_URL_CACHE_TTL_HOURS: {}", e))?;
+        let name_format = env_enum("LUCCA_NAME_FORMAT")?;
+        let key_by = env_enum("LUCCA_KEY_BY")?;
+        let training = env_flag("LUCCA_LEARNING")?;
+        let skip_known = env_flag("LUCCA_SKIP_KNOWN")?;
+        let compress = env_flag("LUCCA_COMPRESS")?;
+        let hex_keys = env_flag("LUCCA_HEX_KEYS")?;
+        let no_cookie_store = env_flag("LUCCA_NO_COOKIE_STORE")?;
+        let session_login = env_flag("LUCCA_SESSION_LOGIN")?;
+
+        Ok(Self {
+            lucca_url: env_var("LUCCA_URL"),
+            email: env_var("LUCCA_EMAIL"),
+            password: env_var("LUCCA_PASSWORD"),
+            training,
+            data_path: env_var("LUCCA_DATA").map(PathBuf::from),
+            strategy,
+            skip_known,
+            unknown_strategy,
+            hash_strategy,
+            compress,
+            overrides_path: env_var("LUCCA_OVERRIDES").map(PathBuf::from),
+            name_format,
+            backups,
+            key_by,
+            ca_cert: env_var("LUCCA_CA_CERT").map(PathBuf::from),
+            url_cache_path: env_var("LUCCA_URL_CACHE").map(PathBuf::from),
+            url_cache_ttl_hours,
+            encoding,
+            stats_path: env_var("LUCCA_STATS").map(PathBuf::from),
+            targets_path: env_var("LUCCA_TARGETS").map(PathBuf::from),
+            min_confidence,
+            hex_keys,
+            image_timeout_ms,
+            human_delay,
+            seed,
+            max_game_retries,
+            fallback_value: env_var("LUCCA_FALLBACK_VALUE"),
+            learn_threshold,
+            min_image_bytes,
+            token_selector: env_var("LUCCA_TOKEN_SELECTOR"),
+            statsd: env_var("LUCCA_STATSD"),
+            cookie_store: no_cookie_store.map(|no| !no),
+            persistent_login: session_login.map(|session| !session),
+        })
+    }
+
+    fn from_args(args: &Args) -> Self {
+        Self {
+            lucca_url: args.url.clone(),
+            email: args.email.clone(),
+            password: None,
+            training: cli_flag(args.training, args.no_training),
+            data_path: args.data.clone(),
+            strategy: args.strategy,
+            skip_known: cli_flag(args.skip_known, args.no_skip_known),
+            unknown_strategy: args.unknown_strategy,
+            hash_strategy: args.hash_strategy,
+            compress: cli_flag(args.compress, args.no_compress),
+            overrides_path: args.overrides.clone(),
+            name_format: args.name_format,
+            backups: args.backups,
+            key_by: args.key_by,
+            ca_cert: args.ca_cert.clone(),
+            url_cache_path: args.url_cache.clone(),
+            url_cache_ttl_hours: args.url_cache_ttl_hours,
+            encoding: args.encoding,
+            stats_path: args.stats.clone(),
+            targets_path: args.targets.clone(),
+            min_confidence: args.min_confidence,
+            hex_keys: cli_flag(args.hex_keys, args.no_hex_keys),
+            image_timeout_ms: args.image_timeout_ms,
+            human_delay: args.human_delay.clone(),
+            seed: args.seed,
+            max_game_retries: args.max_game_retries,
+            fallback_value: args.fallback_value.clone(),
+            learn_threshold: args.learn_threshold,
+            min_image_bytes: args.min_image_bytes,
+            token_selector: args.token_selector.clone(),
+            statsd: args.statsd.clone(),
+            cookie_store: cli_flag(args.cookie_store, args.no_cookie_store),
+            persistent_login: cli_flag(args.persistent_login, args.session_login),
+        }
+    }
+
+    /// Keeps the values set in `self` and takes the others from `lower`.
+    fn or(self, lower: Self) -> Self {
+        Self {
+            lucca_url: self.lucca_url.or(lower.lucca_url),
+            email: self.email.or(lower.email),
+            password: self.password.or(lower.password),
+            training: self.training.or(lower.training),
+            data_path: self.data_path.or(lower.data_path),
+            strategy: self.strategy.or(lower.strategy),
+            skip_known: self.skip_known.or(lower.skip_known),
+            unknown_strategy: self.unknown_strategy.or(lower.unknown_strategy),
+            hash_strategy: self.hash_strategy.or(lower.hash_strategy),
+            compress: self.compress.or(lower.compress),
+            overrides_path: self.overrides_path.or(lower.overrides_path),
+            name_format: self.name_format.or(lower.name_format),
+            backups: self.backups.or(lower.backups),
+            key_by: self.key_by.or(lower.key_by),
+            ca_cert: self.ca_cert.or(lower.ca_cert),
+            url_cache_path: self.url_cache_path.or(lower.url_cache_path),
+            url_cache_ttl_hours: self.url_cache_ttl_hours.or(lower.url_cache_ttl_hours),
+            encoding: self.encoding.or(lower.encoding),
+            stats_path: self.stats_path.or(lower.stats_path),
+            targets_path: self.targets_path.or(lower.targets_path),
+            min_confidence: self.min_confidence.or(lower.min_confidence),
+            hex_keys: self.hex_keys.or(lower.hex_keys),
+            image_timeout_ms: self.image_timeout_ms.or(lower.image_timeout_ms),
+            human_delay: self.human_delay.or(lower.human_delay),
+            seed: self.seed.or(lower.seed),
+            max_game_retries: self.max_game_retries.or(lower.max_game_retries),
+            fallback_value: self.fallback_value.or(lower.fallback_value),
+            learn_threshold: self.learn_threshold.or(lower.learn_threshold),
+            min_image_bytes: self.min_image_bytes.or(lower.min_image_bytes),
+            token_selector: self.token_selector.or(lower.token_selector),
+            statsd: self.statsd.or(lower.statsd),
+            cookie_store: self.cookie_store.or(lower.cookie_store),
+            persistent_login: self.persistent_login.or(lower.persistent_login),
+        }
+    }
+}
+
+/// Resolves the configuration from, by increasing precedence: defaults, the
+/// `--config` TOML file, `LUCCA_*` environment variables and CLI flags.
+pub fn load_config(args: &Args) -> Result<Config> {
+    let file = match &args.config {
+        Some(path) => ConfigLayer::from_file(path)?,
+        None => ConfigLayer::default(),
+    };
+    let layer = ConfigLayer::from_args(args).or(ConfigLayer::from_env()?.or(file));
+    if cfg!(not(feature = "statsd")) && layer.statsd.is_some() {
+        return Err(anyhow!(
+            "Sending metrics to statsd needs a build with the statsd feature"
+        ));
+    }
+
+    Ok(Config {
+        lucca_url: layer.lucca_url,
+        email: layer.email,
+        password: layer.password,
+        training: layer.training.unwrap_or(false),
+        data_path: layer.data_path.unwrap_or(PathBuf::from(HASH_FILE_PATH)),
+        strategy: layer.strategy.unwrap_or_default(),
+        skip_known: layer.skip_known.unwrap_or(false),
+        unknown_strategy: layer.unknown_strategy.unwrap_or_default(),
+        hash_strategy: layer.hash_strategy.unwrap_or_default(),
+        compress: layer.compress.unwrap_or(false),
+        overrides_path: layer.overrides_path,
+        name_format: layer.name_format.unwrap_or_default(),
+        backups: layer.backups.unwrap_or(0),
+        key_by: layer.key_by.unwrap_or_default(),
+        ca_cert: layer.ca_cert,
+        url_cache_path: layer.url_cache_path,
+        url_cache_ttl_hours: layer
+            .url_cache_ttl_hours
+            .unwrap_or(DEFAULT_URL_CACHE_TTL_HOURS),
+        encoding: layer.encoding.unwrap_or_default(),
+        stats_path: layer.stats_path,
+        targets_path: layer.targets_path,
+        min_confidence: layer.min_confidence.unwrap_or(DEFAULT_MIN_CONFIDENCE),
+        hex_keys: layer.hex_keys.unwrap_or(false),
+        image_timeout_ms: layer.image_timeout_ms,
+        human_delay: layer.human_delay,
+        seed: layer.seed,
+        max_game_retries: layer.max_game_retries.unwrap_or(0),
+        fallback_value: layer.fallback_value,
+        learn_threshold: layer.learn_threshold.unwrap_or(1),
+        min_image_bytes: layer.min_image_bytes.unwrap_or(0),
+        token_selector: layer
+            .token_selector
+            .unwrap_or_else(|| DEFAULT_TOKEN_SELECTOR.to_owned()),
+        statsd: layer.statsd,
+        cookie_store: layer.cookie_store.unwrap_or(true),
+        persistent_login: layer.persistent_login.unwrap_or(true),
+    })
+}
+
+impl Config {
+    /// Returns the URL, email and password, which are required to play.
+    pub fn credentials(&self) -> Result<(&str, &str, &str)> {
+        let missing = |name: &str| {
+            anyhow!(
+                "Missing {} (set it in the config file or LUCCA_* variables)",
+                name
+            )
+        };
+        Ok((
+            self.lucca_url
+                .as_deref()
+                .ok_or_else(|| missing("lucca_url"))?,
+            self.email.as_deref().ok_or_else(|| missing("email"))?,
+            self.password
+                .as_deref()
+                .ok_or_else(|| missing("password"))?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    // The only test setting variables, so that no other sees them
+    #[test]
+    fn layers_flags_over_variables_over_the_file_over_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "lucca_url = \"https://file.ilucca.net\"\nemail = \"file@lucca.fr\"\n\
+             data_path = \"file-data\"\ntraining = true\ncompress = true\nhex_keys = true\n",
+        )
+        .unwrap();
+        std::env::set_var("LUCCA_EMAIL", "env@lucca.fr");
+        std::env::set_var("LUCCA_DATA", "env-data");
+        std::env::set_var("LUCCA_NO_COOKIE_STORE", "false");
+        std::env::set_var("LUCCA_SESSION_LOGIN", "1");
+        std::env::set_var("LUCCA_COMPRESS", "false");
+
+        let args = Args::parse_from([
+            "lucca_faces_autoplay_v2",
+            "--config",
+            path.to_str().unwrap(),
+            "--data",
+            "flag-data",
+            "--no-training",
+            "--session-login",
+            "--persistent-login",
+        ]);
+        let config = load_config(&args);
+        std::env::remove_var("LUCCA_EMAIL");
+        std::env::remove_var("LUCCA_DATA");
+        std::env::remove_var("LUCCA_NO_COOKIE_STORE");
+        std::env::remove_var("LUCCA_SESSION_LOGIN");
+        std::env::remove_var("LUCCA_COMPRESS");
+        let config = config.unwrap();
+
+        assert_eq!(config.data_path, PathBuf::from("flag-data"));
+        assert_eq!(config.email.as_deref(), Some("env@lucca.fr"));
+        assert_eq!(config.lucca_url.as_deref(), Some("https://file.ilucca.net"));
+        // Turned off by a flag, by a variable, or left on by the file
+        assert!(!config.training);
+        assert!(!config.compress);
+        assert!(config.hex_keys);
+        assert!(config.cookie_store);
+        // The last of a flag and its opposite wins over the variable
+        assert!(config.persistent_login);
+    }
+
+    #[test]
+    fn reads_flags_set_to_false() {
+        for value in ["1", "true", "Yes", "on"] {
+            assert!(parse_flag("LUCCA_COMPRESS", value).unwrap());
+        }
+        for value in ["", "0", "false", "FALSE", "no", "off"] {
+            assert!(!parse_flag("LUCCA_COMPRESS", value).unwrap());
+        }
+        let error = parse_flag("LUCCA_COMPRESS", "maybe").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid LUCCA_COMPRESS: maybe, expected true or false"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields_in_the_file() {
+        let error = toml::from_str::<ConfigLayer>("emial = \"a@lucca.fr\"").unwrap_err();
+        assert!(error.to_string().contains("unknown field"));
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::anonymize::Anonymizer;
+
+/// How often each name was answered for each correct name, over a game.
+/// Correct answers are on the diagonal, the rest are mix-ups.
+#[derive(Serialize, Debug, Default)]
+pub struct Confusion(BTreeMap<String, BTreeMap<String, u32>>);
+
+/// Quotes a CSV field when it has a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_owned(),
+    }
+}
+
+impl Confusion {
+    pub fn anonymized(self, anonymizer: &Anonymizer) -> Self {
+        Self(
+            self.0
+                .into_iter()
+                .map(|(correct, guesses)| {
+                    let guesses = guesses
+                        .into_iter()
+                        .map(|(guessed, count)| (anonymizer.alias(&guessed), count))
+                        .collect();
+                    (anonymizer.alias(&correct), guesses)
+                })
+                .collect(),
+        )
+    }
+
+    pub fn record(&mut self, correct: &str, guessed: &str) {
+        *self
+            .0
+            .entry(correct.to_owned())
+            .or_default()
+            .entry(guessed.to_owned())
+            .or_default() += 1;
+    }
+
+    /// The matrix as CSV, a row per correct name and a column per guessed
+    /// name, both over every name seen so that it is square. With `long`,
+    /// only the non-zero cells are written, a `correct,guessed,count` line
+    /// each, which is far smaller over a large directory.
+    pub fn to_csv(&self, long: bool) -> String {
+        let mut csv = String::new();
+        if long {
+            csv.push_str("correct,guessed,count\n");
+            for (correct, guesses) in &self.0 {
+                for (guessed, count) in guesses {
+                    csv += &format!("{},{},{}\n", csv_field(correct), csv_field(guessed), count);
+                }
+            }
+            return csv;
+        }
+
+        let names: BTreeSet<&String> = self
+            .0
+            .iter()
+            .flat_map(|(correct, guesses)| std::iter::once(correct).chain(guesses.keys()))
+            .collect();
+        csv.push_str("correct");
+        for name in &names {
+            csv += &format!(",{}", csv_field(name));
+        }
+        csv.push('\n');
+        for correct in &names {
+            csv.push_str(&csv_field(correct));
+            let guesses = self.0.get(*correct);
+            for guessed in &names {
+                let count = guesses.and_then(|g| g.get(*guessed)).copied();
+                csv += &format!(",{}", count.unwrap_or_default());
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
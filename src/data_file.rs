@@ -0,0 +1,332 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    error::PlayerError,
+    evaluate::Fixture,
+    hashing::{hash_image, HashStrategy},
+    storage::{read_text, write_text},
+};
+
+/// Version of the format written by `save_data_file`. Version 2 counts the
+/// votes of each face, version 1 files being read as a single vote.
+pub const DATA_FILE_VERSION: u32 = 2;
+
+/// How often each name was the correct answer for a face. Occasional wrong
+/// labels, or faces colliding with another, are outvoted over the sightings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Votes(BTreeMap<String, u32>);
+
+/// Votes as written: a single vote is a bare name, as before votes existed.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawVotes<'a> {
+    Name(Cow<'a, str>),
+    Votes(Cow<'a, BTreeMap<String, u32>>),
+}
+
+impl Votes {
+    pub fn new(name: String) -> Self {
+        Self(BTreeMap::from([(name, 1)]))
+    }
+
+    pub fn vote(&mut self, name: &str) {
+        *self.0.entry(name.to_owned()).or_default() += 1;
+    }
+
+    pub fn count(&self, name: &str) -> u32 {
+        self.0.get(name).copied().unwrap_or_default()
+    }
+
+    /// The most voted name, the first in alphabetical order among equals.
+    pub fn majority(&self) -> Option<&String> {
+        self.0
+            .iter()
+            .rev()
+            .max_by_key(|&(_, &count)| count)
+            .map(|(name, _)| name)
+    }
+}
+
+impl Serialize for Votes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.iter().next() {
+            Some((name, 1)) if self.0.len() == 1 => RawVotes::Name(Cow::Borrowed(name)),
+            _ => RawVotes::Votes(Cow::Borrowed(&self.0)),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Votes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match RawVotes::deserialize(deserializer)? {
+            RawVotes::Name(name) => Self::new(name.into_owned()),
+            RawVotes::Votes(votes) => Self(votes.into_owned()),
+        })
+    }
+}
+
+/// The most voted name of each face.
+pub fn names(faces: &HashMap<u64, Votes>) -> HashMap<u64, String> {
+    confirmed_names(faces, 1)
+}
+
+/// The most voted name of each face, for the faces whose name got at least
+/// `min_votes` votes.
+pub fn confirmed_names(faces: &HashMap<u64, Votes>, min_votes: u32) -> HashMap<u64, String> {
+    faces
+        .iter()
+        .filter_map(|(&hash, votes)| {
+            let name = votes
+                .majority()
+                .filter(|name| votes.count(name) >= min_votes)?;
+            Some((hash, name.clone()))
+        })
+        .collect()
+}
+
+/// The learned faces along with how their keys were computed. Files written
+/// before the header existed are a bare map, and have no `strategy`.
+#[derive(Deserialize, Debug, Default)]
+pub struct DataFile {
+    pub version: u32,
+    pub strategy: Option<HashStrategy>,
+    #[serde(deserialize_with = "deserialize_faces")]
+    pub faces: HashMap<u64, Votes>,
+}
+
+/// A key as written in the data file, either a number or a hexadecimal
+/// string.
+#[derive(Deserialize, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+enum Key {
+    Decimal(u64),
+    Hex(String),
+}
+
+fn deserialize_faces<'de, D>(deserializer: D) -> Result<HashMap<u64, Votes>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    HashMap::<Key, Votes>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, votes)| match key {
+            Key::Decimal(hash) => Ok((hash, votes)),
+            Key::Hex(hex) => u64::from_str_radix(&hex, 16)
+                .map(|hash| (hash, votes))
+                .map_err(|_| serde::de::Error::custom(format!("invalid key {}", hex))),
+        })
+        .collect()
+}
+
+impl DataFile {
+    /// Reads the file at `path`, an empty map when there is none.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = read_text(path)?;
+        if !text.trim_start().starts_with('(') {
+            return Ok(Self {
+                version: 0,
+                strategy: None,
+                faces: ron::from_str(&text)?,
+            });
+        }
+        let data_file: Self = ron::from_str(&text)?;
+        if data_file.version > DATA_FILE_VERSION {
+            return Err(anyhow!(
+                "{} is in format {}, newer than the supported {}",
+                path.display(),
+                data_file.version,
+                DATA_FILE_VERSION
+            ));
+        }
+        Ok(data_file)
+    }
+
+    /// The faces, provided their keys were computed with `strategy`. Files
+    /// without a header may be keyed with the hasher used before XXH3,
+    /// which no image matches anymore, so they are refused.
+    pub fn faces_for(self, strategy: HashStrategy) -> Result<HashMap<u64, Votes>, PlayerError> {
+        match self.strategy {
+            Some(stored) if stored != strategy => Err(PlayerError::StrategyMismatch {
+                stored,
+                configured: strategy,
+            }),
+            None if !self.faces.is_empty() => Err(PlayerError::Config(anyhow!(
+                "A map without a header predates XXH3 keys, none of its faces would be \
+                 recognized: learn it again"
+            ))),
+            _ => Ok(self.faces),
+        }
+    }
+}
+
+/// The faces as written, the keys being hexadecimal strings when asked for.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Faces<'a> {
+    Decimal(&'a HashMap<u64, Votes>),
+    Hex(BTreeMap<String, &'a Votes>),
+}
+
+/// What `DataFile` serializes as, without copying the map.
+#[derive(Serialize)]
+struct DataFileRef<'a> {
+    version: u32,
+    strategy: Option<HashStrategy>,
+    faces: Faces<'a>,
+}
+
+/// Writes the faces under a header, their keys as zero-padded hexadecimal
+/// strings with `hex_keys`, which is easier to compare with debug logs.
+pub fn save_data_file(
+    path: &Path,
+    strategy: HashStrategy,
+    faces: &HashMap<u64, Votes>,
+    compress: bool,
+    hex_keys: bool,
+) -> Result<()> {
+    let faces = match hex_keys {
+        true => Faces::Hex(
+            faces
+                .iter()
+                .map(|(hash, votes)| (format!("{:016x}", hash), votes))
+                .collect(),
+        ),
+        false => Faces::Decimal(faces),
+    };
+    let data_file = DataFileRef {
+        version: DATA_FILE_VERSION,
+        strategy: Some(strategy),
+        faces,
+    };
+    write_text(path, &ron::to_string(&data_file)?, compress)
+}
+
+/// How many faces `convert` carried over to the new strategy.
+#[derive(Debug)]
+pub struct Conversion {
+    pub converted: usize,
+    /// Faces none of the fixtures showed, they have to be learned again
+    pub dropped: usize,
+}
+
+/// Rekeys `faces`, learned with `from`, to `to` using labeled images: the
+/// keys can't be converted without the images they were computed from, so
+/// only the faces shown by a fixture are kept.
+pub fn convert(
+    faces: &HashMap<u64, Votes>,
+    fixtures: &[Fixture],
+    from: HashStrategy,
+    to: HashStrategy,
+) -> (HashMap<u64, Votes>, Conversion) {
+    let mut converted = HashMap::new();
+    let mut matched = HashSet::new();
+    for fixture in fixtures {
+        let key = hash_image(&fixture.image, from);
+        if let Some(votes) = faces.get(&key) {
+            converted.insert(hash_image(&fixture.image, to), votes.clone());
+            matched.insert(key);
+        }
+    }
+
+    let conversion = Conversion {
+        converted: matched.len(),
+        dropped: faces.len() - matched.len(),
+    };
+    (converted, conversion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outvotes_a_minority_label() {
+        let mut votes = Votes::new("Bruno Petit".to_owned());
+        votes.vote("Alice Martin");
+        // Tied, the first in alphabetical order wins
+        assert_eq!(votes.majority().unwrap(), "Alice Martin");
+        votes.vote("Bruno Petit");
+        assert_eq!(votes.majority().unwrap(), "Bruno Petit");
+        votes.vote("Alice Martin");
+        votes.vote("Alice Martin");
+        assert_eq!(votes.majority().unwrap(), "Alice Martin");
+        assert_eq!(votes.count("Alice Martin"), 3);
+        assert_eq!(votes.count("Chloé Durand"), 0);
+        assert_eq!(Votes::default().majority(), None);
+    }
+
+    #[test]
+    fn reads_single_names_as_one_vote() {
+        let faces: HashMap<u64, Votes> =
+            ron::from_str(r#"{1: "Alice Martin", 2: {"Alice Martin": 1, "Bruno Petit": 3}}"#)
+                .unwrap();
+        assert_eq!(faces[&1], Votes::new("Alice Martin".to_owned()));
+        assert_eq!(faces[&2].count("Bruno Petit"), 3);
+
+        // Written back the same way
+        let text = ron::to_string(&faces[&1]).unwrap();
+        assert_eq!(text, r#""Alice Martin""#);
+        assert_eq!(
+            ron::from_str::<Votes>(&ron::to_string(&faces[&2]).unwrap()).unwrap(),
+            faces[&2]
+        );
+
+        let names = names(&faces);
+        assert_eq!(names[&1], "Alice Martin");
+        assert_eq!(names[&2], "Bruno Petit");
+    }
+
+    #[test]
+    fn refuses_headerless_maps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("legacy.ron");
+        std::fs::write(&path, "{1: \"Alice Martin\"}").unwrap();
+
+        for strategy in [HashStrategy::Bytes, HashStrategy::Full] {
+            let error = DataFile::load(&path)
+                .unwrap()
+                .faces_for(strategy)
+                .unwrap_err();
+            assert!(matches!(error, PlayerError::Config(_)));
+            assert!(error.to_string().contains("learn it again"));
+        }
+
+        // Nothing learned yet, nothing to mix up
+        let missing = DataFile::load(&dir.path().join("missing.ron")).unwrap();
+        assert!(missing.faces_for(HashStrategy::Bytes).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_maps_of_another_strategy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("full.ron");
+        let faces = HashMap::from([(1, Votes::new("Alice Martin".to_owned()))]);
+        save_data_file(&path, HashStrategy::Full, &faces, false, false).unwrap();
+
+        let error = DataFile::load(&path)
+            .unwrap()
+            .faces_for(HashStrategy::Bytes)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            PlayerError::StrategyMismatch {
+                stored: HashStrategy::Full,
+                configured: HashStrategy::Bytes,
+            }
+        ));
+        let loaded = DataFile::load(&path).unwrap();
+        assert_eq!(loaded.faces_for(HashStrategy::Full).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,38 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use html_escape::encode_text;
+
+use crate::{evaluate::Fixture, interactive::extension};
+
+/// Names stay hidden until their card is clicked, so that the page works as
+/// flashcards.
+const DECK_STYLE: &str = "body{font-family:sans-serif;display:flex;flex-wrap:wrap;gap:1em}\
+    figure{margin:0;width:160px;cursor:pointer;text-align:center}\
+    img{width:160px;height:160px;object-fit:cover;border-radius:8px}\
+    figure:not(.shown) figcaption{visibility:hidden}";
+const DECK_SCRIPT: &str = "document.querySelectorAll('figure').forEach(f=>\
+    f.addEventListener('click',()=>f.classList.toggle('shown')))";
+
+/// A single HTML page showing every labeled image along with its name, the
+/// images being embedded so that the page can be shared on its own.
+pub fn render_deck(fixtures: &[Fixture]) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Faces</title>\n\
+         <style>{}</style>\n</head>\n<body>\n",
+        DECK_STYLE
+    );
+    for fixture in fixtures {
+        let media_type = match extension(&fixture.image) {
+            "jpg" => "jpeg",
+            extension => extension,
+        };
+        html += &format!(
+            "<figure><img src=\"data:image/{};base64,{}\" alt=\"\"><figcaption>{}\
+             </figcaption></figure>\n",
+            media_type,
+            BASE64_STANDARD.encode(&fixture.image),
+            encode_text(&fixture.label)
+        );
+    }
+    html += &format!("<script>{}</script>\n</body>\n</html>\n", DECK_SCRIPT);
+    html
+}
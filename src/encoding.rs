@@ -0,0 +1,88 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use reqwest::blocking::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How the bodies of the game requests are encoded. The login form is always
+/// form-urlencoded, as it mimics the HTML page.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BodyEncoding {
+    /// `application/json`, what Lucca expects at the time of writing
+    #[default]
+    Json,
+    /// `application/x-www-form-urlencoded`, arrays being sent as repeated
+    /// keys
+    Form,
+}
+
+/// Flattens a JSON object into form fields, an array giving one field per
+/// element.
+fn form_fields(body: Value) -> Vec<(String, String)> {
+    let Value::Object(fields) = body else {
+        return vec![];
+    };
+
+    let mut pairs = vec![];
+    for (key, value) in fields {
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                Value::String(s) => s,
+                Value::Null => String::new(),
+                value => value.to_string(),
+            };
+            pairs.push((key.clone(), value));
+        }
+    }
+    pairs
+}
+
+impl BodyEncoding {
+    pub fn apply<T: Serialize>(&self, request: RequestBuilder, body: &T) -> Result<RequestBuilder> {
+        Ok(match self {
+            BodyEncoding::Json => request.json(body),
+            BodyEncoding::Form => request.form(&form_fields(serde_json::to_value(body)?)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::blocking::{Client, Request};
+    use serde_json::json;
+
+    fn encoded(encoding: BodyEncoding) -> Request {
+        let request = Client::new().post("http://localhost/faces/api/games/g1/guess");
+        let body = json!({"questionId": 3, "suggestionId": 7, "tags": ["a", "b"]});
+        encoding.apply(request, &body).unwrap().build().unwrap()
+    }
+
+    fn content_type(request: &Request) -> &str {
+        request.headers()["Content-Type"].to_str().unwrap()
+    }
+
+    fn body(request: &Request) -> &str {
+        std::str::from_utf8(request.body().unwrap().as_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn sends_json_by_default() {
+        let request = encoded(BodyEncoding::default());
+        assert_eq!(content_type(&request), "application/json");
+        let sent: Value = serde_json::from_str(body(&request)).unwrap();
+        assert_eq!(sent["suggestionId"], 7);
+    }
+
+    #[test]
+    fn sends_forms_with_repeated_keys() {
+        let request = encoded(BodyEncoding::Form);
+        assert_eq!(content_type(&request), "application/x-www-form-urlencoded");
+        assert_eq!(body(&request), "questionId=3&suggestionId=7&tags=a&tags=b");
+    }
+}
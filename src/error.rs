@@ -0,0 +1,35 @@
+use thiserror::Error;
+
+use crate::hashing::HashStrategy;
+
+/// Failures worth telling apart from the generic ones, which are reported
+/// through `anyhow`.
+#[derive(Error, Debug)]
+pub enum PlayerError {
+    #[error("Lucca appears to be under maintenance, try again later: {title}")]
+    Maintenance { title: String },
+    #[error("Authentication failed: {0}")]
+    Auth(String),
+    #[error(
+        "Lucca asks for a second factor to log in, which can't be automated: use an account \
+         without multi-factor authentication"
+    )]
+    MfaRequired,
+    #[error(
+        "Lucca asks to solve a CAPTCHA to log in: log in once from a browser, then try again \
+         later"
+    )]
+    CaptchaRequired,
+    #[error("Invalid configuration: {0:#}")]
+    Config(anyhow::Error),
+    #[error("Accuracy of {accuracy:.2} below the minimum of {min:.2}")]
+    LowAccuracy { accuracy: f64, min: f64 },
+    #[error(
+        "The data file was learned with the {stored} hash strategy but {configured} is \
+         configured, learn it again or convert it with --convert-strategy"
+    )]
+    StrategyMismatch {
+        stored: HashStrategy,
+        configured: HashStrategy,
+    },
+}
@@ -0,0 +1,283 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs::{read, read_dir, read_to_string},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    anonymize::Anonymizer,
+    hashing::{hash_all, HashStrategy},
+    names::{KeyBy, NameFormat},
+    player::Suggestion,
+    strategy::{choose_suggestion, LearningStrategy, UnknownStrategy},
+};
+
+const LABELS_FILE: &str = "labels.ron";
+
+#[derive(Serialize, Debug, Default)]
+pub struct NameEvaluation {
+    pub images: usize,
+    pub predicted: usize,
+    pub correct: usize,
+    pub precision: f64,
+    pub recall: f64,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Evaluation {
+    pub images: usize,
+    pub predicted: usize,
+    pub correct: usize,
+    pub precision: f64,
+    pub recall: f64,
+    pub names: BTreeMap<String, NameEvaluation>,
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    match denominator {
+        0 => 0.,
+        _ => numerator as f64 / denominator as f64,
+    }
+}
+
+impl Evaluation {
+    pub fn anonymized(mut self, anonymizer: &Anonymizer) -> Self {
+        self.names = self
+            .names
+            .into_iter()
+            .map(|(name, evaluation)| (anonymizer.alias(&name), evaluation))
+            .collect();
+        self
+    }
+}
+
+/// A labeled image, as listed in `labels.ron`.
+pub struct Fixture {
+    pub label: String,
+    pub image: Vec<u8>,
+}
+
+/// Reads every image of `dir` along with its label, ordered by file name.
+pub fn load_fixtures(dir: &Path) -> Result<Vec<Fixture>> {
+    let labels: HashMap<String, String> =
+        ron::from_str(read_to_string(dir.join(LABELS_FILE))?.as_str())?;
+
+    let mut fixtures = BTreeMap::new();
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        if file_name == LABELS_FILE {
+            continue;
+        }
+        let label = labels
+            .get(file_name)
+            .ok_or(anyhow!("No label found for {}", file_name))?;
+        let fixture = Fixture {
+            label: label.clone(),
+            image: read(&path)?,
+        };
+        fixtures.insert(file_name.to_owned(), fixture);
+    }
+
+    Ok(fixtures.into_values().collect())
+}
+
+/// Runs every fixture through the map, the same way a live game would, and
+/// counts how often the stored name is right.
+pub fn evaluate(
+    hash_map: &HashMap<u64, String>,
+    fixtures: &[Fixture],
+    hash_strategy: HashStrategy,
+) -> Evaluation {
+    let mut evaluation = Evaluation::default();
+    let images: Vec<&[u8]> = fixtures.iter().map(|f| f.image.as_slice()).collect();
+    let hashes = hash_all(&images, hash_strategy);
+    for (fixture, image_hash) in fixtures.iter().zip(hashes) {
+        let label = &fixture.label;
+        let prediction = hash_map.get(&image_hash);
+        let is_correct = prediction == Some(label);
+
+        evaluation.images += 1;
+        evaluation.names.entry(label.clone()).or_default().images += 1;
+        if let Some(name) = prediction {
+            evaluation.predicted += 1;
+            evaluation.names.entry(name.clone()).or_default().predicted += 1;
+        }
+        if is_correct {
+            evaluation.correct += 1;
+            evaluation.names.entry(label.clone()).or_default().correct += 1;
+        }
+    }
+
+    evaluation.precision = ratio(evaluation.correct, evaluation.predicted);
+    evaluation.recall = ratio(evaluation.correct, evaluation.images);
+    for name in evaluation.names.values_mut() {
+        name.precision = ratio(name.correct, name.predicted);
+        name.recall = ratio(name.correct, name.images);
+    }
+
+    evaluation
+}
+
+/// A combination of strategies to compare, written `<hash>:<unknown>`.
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct Strategies {
+    pub hash: HashStrategy,
+    pub unknown: UnknownStrategy,
+}
+
+impl FromStr for Strategies {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hash, unknown) = s
+            .split_once(':')
+            .ok_or(format!("expected <hash>:<unknown>, got {}", s))?;
+        Ok(Self {
+            hash: HashStrategy::from_str(hash, true)?,
+            unknown: UnknownStrategy::from_str(unknown, true)?,
+        })
+    }
+}
+
+impl Display for Strategies {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = |value: Option<clap::builder::PossibleValue>| {
+            value.map(|v| v.get_name().to_owned()).unwrap_or_default()
+        };
+        write!(
+            f,
+            "{}:{}",
+            name(self.hash.to_possible_value()),
+            name(self.unknown.to_possible_value())
+        )
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct StrategyEvaluation {
+    pub strategies: Strategies,
+    /// Share of the images found in the map
+    pub coverage: f64,
+    /// Share of the simulated questions answered correctly
+    pub accuracy: f64,
+}
+
+/// Builds the suggestions of a simulated question: the fixture's label and
+/// up to three other labels, the correct one moving from question to
+/// question so that no position is favoured.
+fn simulated_suggestions(names: &[&String], index: usize, label: &String) -> Vec<Suggestion> {
+    let position = names.iter().position(|n| *n == label).unwrap_or(0);
+    let mut values: Vec<String> = (1..names.len().min(4))
+        .map(|k| names[(position + k) % names.len()].clone())
+        .collect();
+    values.insert(index % (values.len() + 1), label.clone());
+
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(id, value)| Suggestion {
+            id: id as u32,
+            value,
+        })
+        .collect()
+}
+
+/// Answers a simulated question for every fixture with each combination of
+/// strategies, so that they can be compared on the same data.
+pub fn compare(
+    hash_map: &HashMap<u64, String>,
+    fixtures: &[Fixture],
+    combinations: &[Strategies],
+) -> Vec<StrategyEvaluation> {
+    let images: Vec<&[u8]> = fixtures.iter().map(|f| f.image.as_slice()).collect();
+    let mut names: Vec<&String> = fixtures.iter().map(|f| &f.label).collect();
+    names.sort();
+    names.dedup();
+
+    combinations
+        .iter()
+        .map(|&strategies| {
+            let mut known = 0;
+            let mut correct = 0;
+            let hashes = hash_all(&images, strategies.hash);
+            for (i, (fixture, image_hash)) in fixtures.iter().zip(hashes).enumerate() {
+                let suggestions = simulated_suggestions(&names, i, &fixture.label);
+                known += hash_map.contains_key(&image_hash) as usize;
+                let suggestion = choose_suggestion(
+                    hash_map,
+                    &suggestions,
+                    hash_map.get(&image_hash).map(String::as_str),
+                    LearningStrategy::Score,
+                    strategies.unknown,
+                    &NameFormat::Exact,
+                    KeyBy::Name,
+                );
+                correct += (suggestion.value == fixture.label) as usize;
+            }
+
+            StrategyEvaluation {
+                strategies,
+                coverage: ratio(known, fixtures.len()),
+                accuracy: ratio(correct, fixtures.len()),
+            }
+        })
+        .collect()
+}
+
+pub struct Comparison<'a>(pub &'a [StrategyEvaluation]);
+
+impl Display for Comparison<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<24} {:>8} {:>8}",
+            "Strategies", "Coverage", "Accuracy"
+        )?;
+        for evaluation in self.0 {
+            write!(
+                f,
+                "\n{:<24} {:>8.2} {:>8.2}",
+                evaluation.strategies.to_string(),
+                evaluation.coverage,
+                evaluation.accuracy
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Evaluation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<32} {:>6} {:>9} {:>7} {:>9} {:>6}",
+            "Name", "Images", "Predicted", "Correct", "Precision", "Recall"
+        )?;
+        for (name, evaluation) in &self.names {
+            writeln!(
+                f,
+                "{:<32} {:>6} {:>9} {:>7} {:>9.2} {:>6.2}",
+                name,
+                evaluation.images,
+                evaluation.predicted,
+                evaluation.correct,
+                evaluation.precision,
+                evaluation.recall
+            )?;
+        }
+        write!(
+            f,
+            "{:<32} {:>6} {:>9} {:>7} {:>9.2} {:>6.2}",
+            "Total", self.images, self.predicted, self.correct, self.precision, self.recall
+        )
+    }
+}
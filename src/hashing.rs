@@ -0,0 +1,182 @@
+use std::fmt::Display;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+pub const IMAGE_BYTES: usize = 1024;
+
+/// Keys are computed with XXH3, whose output is specified independently of
+/// the platform, so that maps can be shared between machines. `ahash` was
+/// used before, but it switches to AES instructions where they are available
+/// (e.g. on Apple silicon) and its output isn't stable across versions; maps
+/// learned with it have to be learned again.
+const SEED: u64 = 10960905448801897020;
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    xxh3_64_with_seed(bytes, SEED)
+}
+
+/// What part of an image its key is computed from. A map only matches the
+/// strategy it was trained with.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashStrategy {
+    /// Hash the first bytes of the image, only those are downloaded
+    #[default]
+    Bytes,
+    /// Hash the whole image, at the cost of downloading it entirely
+    Full,
+    /// Hash the `ETag`, `Last-Modified` and `Content-Length` of the image,
+    /// which a HEAD request gets without downloading it. Images served
+    /// without the first two, embedded or read from disk are hashed like
+    /// with `Bytes`, so evaluations only cover those
+    Headers,
+}
+
+impl Display for HashStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => write!(f, "{}", value.get_name()),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// A hash as shown in the reports and logs, in zero-padded hexadecimal like
+/// the keys of `--hex-keys` data files. The `0x` prefix has `parse_hash` read
+/// it back as hexadecimal.
+pub struct DisplayHash(pub u64);
+
+impl Display for DisplayHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#018x}", self.0)
+    }
+}
+
+/// Parses a hash given on the command line, either displayed as by
+/// `DisplayHash` or in decimal, as stored in the data files by default.
+pub fn parse_hash(s: &str) -> Result<u64, String> {
+    let parsed = match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|e| format!("invalid hash {}: {}", s, e))
+}
+
+/// Hashes an image the same way whether it was fetched from Lucca or read
+/// from disk: with `HashStrategy::Bytes`, only the first `IMAGE_BYTES` bytes
+/// are taken into account.
+pub fn hash_image(image: &[u8], strategy: HashStrategy) -> u64 {
+    match strategy {
+        HashStrategy::Bytes | HashStrategy::Headers => {
+            hash_bytes(&image[..image.len().min(IMAGE_BYTES)])
+        }
+        HashStrategy::Full => hash_bytes(image),
+    }
+}
+
+/// Hashes the metadata of an image for `HashStrategy::Headers`, if it has an
+/// `ETag` or a `Last-Modified`: its length alone would tell few images apart.
+pub fn hash_headers(
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+    length: Option<&str>,
+) -> Option<u64> {
+    if etag.is_none() && last_modified.is_none() {
+        return None;
+    }
+    let metadata = [etag, last_modified, length].map(|h| h.unwrap_or_default());
+    Some(hash_bytes(metadata.join("\n").as_bytes()))
+}
+
+/// Hashes a batch of images, in parallel with the `parallel` feature.
+pub fn hash_all<T: AsRef<[u8]> + Sync>(images: &[T], strategy: HashStrategy) -> Vec<u64> {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        images
+            .par_iter()
+            .map(|i| hash_image(i.as_ref(), strategy))
+            .collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    images
+        .iter()
+        .map(|i| hash_image(i.as_ref(), strategy))
+        .collect()
+}
+
+/// Combines the hashes of all the images of a question into a single key.
+/// The key does not depend on the order of the images, and a single image
+/// keeps its own hash so that existing maps remain valid.
+pub fn combine_hashes(mut hashes: Vec<u64>) -> u64 {
+    if hashes.len() == 1 {
+        return hashes[0];
+    }
+    hashes.sort_unstable();
+    // Little-endian, so that the key doesn't depend on the platform either
+    let bytes: Vec<u8> = hashes.iter().flat_map(|h| h.to_le_bytes()).collect();
+    hash_bytes(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keys stored in the data files: any change to these values means that
+    // every map has to be learned again
+    #[test]
+    fn keeps_the_keys_of_known_images() {
+        let short = b"lucca faces";
+        let long: Vec<u8> = (0..2048u32).map(|i| (i % 251) as u8).collect();
+        for strategy in [
+            HashStrategy::Bytes,
+            HashStrategy::Full,
+            HashStrategy::Headers,
+        ] {
+            assert_eq!(hash_image(short, strategy), 2730487761645986412);
+        }
+        assert_eq!(hash_image(&long, HashStrategy::Bytes), 1758433721705898404);
+        assert_eq!(
+            hash_image(&long, HashStrategy::Headers),
+            1758433721705898404
+        );
+        assert_eq!(hash_image(&long, HashStrategy::Full), 1593056089103265961);
+        assert_eq!(
+            hash_image(&long[..IMAGE_BYTES], HashStrategy::Full),
+            hash_image(&long, HashStrategy::Bytes)
+        );
+        assert_eq!(hash_image(b"", HashStrategy::Full), 12118622907321768728);
+    }
+
+    #[test]
+    fn keeps_the_keys_of_known_headers() {
+        let headers = hash_headers(
+            Some("\"abc\""),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some("5234"),
+        );
+        assert_eq!(headers, Some(4474033188286723483));
+        assert_eq!(hash_headers(None, None, Some("5234")), None);
+    }
+
+    #[test]
+    fn combines_hashes_regardless_of_their_order() {
+        assert_eq!(combine_hashes(vec![1, 2, 3]), 1288475064745928457);
+        assert_eq!(combine_hashes(vec![3, 1, 2]), 1288475064745928457);
+        assert_eq!(combine_hashes(vec![2, 3, 1]), 1288475064745928457);
+        assert_eq!(combine_hashes(vec![42]), 42);
+    }
+
+    #[test]
+    fn parses_hashes_as_displayed_or_in_decimal() {
+        let hash = 1593056089103265961;
+        let displayed = DisplayHash(hash).to_string();
+        assert_eq!(displayed, "0x161bac129a45f0a9");
+        assert_eq!(parse_hash(&displayed), Ok(hash));
+        assert_eq!(parse_hash("1593056089103265961"), Ok(hash));
+        assert_eq!(DisplayHash(255).to_string(), "0x00000000000000ff");
+        assert!(parse_hash("161bac129a45f0a9").is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use std::{fmt::Display, ops::Range, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Range of the think time waited before each answer, written in
+/// milliseconds as `MIN-MAX`, so that answers aren't all given in a few
+/// milliseconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HumanDelay(pub Range<Duration>);
+
+impl FromStr for HumanDelay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s
+            .split_once('-')
+            .ok_or(format!("expected <min>-<max> in milliseconds, got {}", s))?;
+        let parse = |ms: &str| {
+            ms.trim()
+                .parse()
+                .map(Duration::from_millis)
+                .map_err(|e| format!("invalid delay {}: {}", ms, e))
+        };
+        let (min, max) = (parse(min)?, parse(max)?);
+        if min > max {
+            return Err(format!("the minimum delay {} is above the maximum", s));
+        }
+        Ok(Self(min..max))
+    }
+}
+
+impl Display for HumanDelay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.0.start.as_millis(), self.0.end.as_millis())
+    }
+}
+
+impl Serialize for HumanDelay {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDelay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    env::temp_dir,
+    fs::write,
+    io::{stdin, stdout, IsTerminal, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal,
+};
+
+use crate::player::Suggestion;
+
+/// Whether faces can be labeled by hand, which needs a terminal to prompt on.
+pub fn is_available() -> bool {
+    stdin().is_terminal() && stdout().is_terminal()
+}
+
+pub fn extension(image: &[u8]) -> &'static str {
+    match image {
+        [0x89, b'P', b'N', b'G', ..] => "png",
+        [b'G', b'I', b'F', ..] => "gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        _ => "jpg",
+    }
+}
+
+fn open_in_viewer(path: &Path) -> Result<()> {
+    let mut command = match std::env::consts::OS {
+        "macos" => Command::new("open"),
+        "windows" => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        _ => Command::new("xdg-open"),
+    };
+    command
+        .arg(path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// Writes the images of question `id` to the temporary directory and opens
+/// them in the default viewer.
+pub fn show_images(id: u32, images: &[Vec<u8>]) -> Result<()> {
+    for (i, image) in images.iter().enumerate() {
+        let path = temp_dir().join(format!("lucca-face-{}-{}.{}", id, i, extension(image)));
+        write(&path, image)?;
+        open_in_viewer(&path)?;
+    }
+    Ok(())
+}
+
+/// Leaves the terminal in raw mode for as long as it lives, so that keys are
+/// read as they are pressed.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Waits for a digit between 1 and `count`, or Enter.
+fn read_key(count: usize) -> Result<Option<usize>> {
+    let _raw_mode = RawMode::enable()?;
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(None),
+            // Raw mode keeps Ctrl-C from interrupting the process
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Err(anyhow!("Interrupted"))
+            }
+            KeyCode::Char(c) => match c.to_digit(10) {
+                Some(n) if (1..=count).contains(&(n as usize)) => return Ok(Some(n as usize)),
+                _ => continue,
+            },
+            _ => continue,
+        }
+    }
+}
+
+/// Reads a line holding a number between 1 and `count`, an empty one giving
+/// `None`.
+fn read_number(count: usize) -> Result<Option<usize>> {
+    loop {
+        let mut answer = String::new();
+        if stdin().read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => return Ok(Some(n)),
+            _ => print!("Expected a number between 1 and {}: ", count),
+        }
+        stdout().flush()?;
+    }
+}
+
+/// Asks which suggestion is the face being shown, with a single key press
+/// when there are at most 9 of them. Enter accepts `best`, the suggestion of
+/// the strategies, which is what `None` stands for.
+pub fn ask_suggestion<'a>(
+    suggestions: &'a [Suggestion],
+    best: &Suggestion,
+) -> Result<Option<&'a Suggestion>> {
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        println!("  {}. {}", i + 1, suggestion.value);
+    }
+    print!(
+        "Who is it? [1-{}, Enter for {}] ",
+        suggestions.len(),
+        best.value
+    );
+    stdout().flush()?;
+
+    let choice = match suggestions.len() {
+        ..=9 => read_key(suggestions.len())?,
+        count => read_number(count)?,
+    };
+    let chosen = choice.map(|n| &suggestions[n - 1]);
+    println!("{}", chosen.unwrap_or(best).value);
+    Ok(chosen)
+}
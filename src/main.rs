@@ -1,33 +1,616 @@
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
 use dotenv::dotenv;
 
+mod analysis;
+mod anonymize;
+mod cli;
+mod config;
+mod confusion;
+mod data_file;
+mod deck;
+mod encoding;
+mod error;
+mod evaluate;
+mod hashing;
+mod human_delay;
+mod interactive;
+mod names;
 mod player;
+mod stats;
+#[cfg(feature = "statsd")]
+mod statsd;
+mod storage;
+mod strategy;
+mod summary;
+mod timings;
+mod transport;
+mod url_cache;
+
+use anonymize::Anonymizer;
+use cli::{Args, Format};
+use config::{load_config, Config};
+use data_file::{convert, save_data_file, DataFile};
+use error::PlayerError;
+use evaluate::{compare, evaluate, load_fixtures, Comparison};
+use hashing::{parse_hash, DisplayHash};
+use player::{load_hash_map, Player, PlayerOptions};
+use storage::{rotate_backups, should_compress, write_atomic};
+use summary::{GameSummary, RunSummary};
+use timings::Bench;
+
+/// Exit codes, for schedulers to tell failures apart
+const EXIT_AUTH: u8 = 1;
+const EXIT_NETWORK: u8 = 2;
+const EXIT_CONFIG: u8 = 3;
+const EXIT_MAINTENANCE: u8 = 4;
+const EXIT_OTHER: u8 = 5;
+const EXIT_LOW_ACCURACY: u8 = 6;
 
-use player::Player;
+fn exit_code(error: &anyhow::Error) -> u8 {
+    match error.downcast_ref::<PlayerError>() {
+        Some(PlayerError::Auth(_) | PlayerError::MfaRequired | PlayerError::CaptchaRequired) => {
+            EXIT_AUTH
+        }
+        Some(PlayerError::Config(_) | PlayerError::StrategyMismatch { .. }) => EXIT_CONFIG,
+        Some(PlayerError::Maintenance { .. }) => EXIT_MAINTENANCE,
+        Some(PlayerError::LowAccuracy { .. }) => EXIT_LOW_ACCURACY,
+        None if error.chain().any(|e| e.is::<reqwest::Error>()) => EXIT_NETWORK,
+        None => EXIT_OTHER,
+    }
+}
 
-fn main() {
+fn main() -> ExitCode {
     dotenv().ok();
+    env_logger::init();
+    let args = Args::parse();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+fn run_evaluation(
+    args: &Args,
+    config: &Config,
+    dir: &Path,
+    anonymizer: Option<&Anonymizer>,
+) -> Result<()> {
+    let hash_map = load_hash_map(&config.data_path)?;
+    let fixtures = load_fixtures(dir)?;
+    let report = match args.compare.is_empty() {
+        true => {
+            let mut evaluation = evaluate(&hash_map, &fixtures, config.hash_strategy);
+            if let Some(anonymizer) = anonymizer {
+                evaluation = evaluation.anonymized(anonymizer);
+            }
+            println!("{}", evaluation);
+            serde_json::to_string_pretty(&evaluation)?
+        }
+        false => {
+            let evaluations = compare(&hash_map, &fixtures, &args.compare);
+            println!("{}", Comparison(&evaluations));
+            serde_json::to_string_pretty(&evaluations)?
+        }
+    };
+    if let Some(path) = &args.report {
+        std::fs::write(path, report)?;
+    }
+
+    Ok(())
+}
+
+/// Rekeys the data file, which the player refuses to load when its strategy
+/// doesn't match the configured one.
+fn run_conversion(config: &Config, dir: &Path) -> Result<()> {
+    let path = &config.data_path;
+    let data_file = DataFile::load(path)?;
+    let Some(stored) = data_file.strategy else {
+        return Err(anyhow!(
+            "{} has no header: it predates XXH3 keys and can't be converted, learn it again",
+            path.display()
+        ));
+    };
+    if stored == config.hash_strategy {
+        println!("{} already uses the {} strategy", path.display(), stored);
+        return Ok(());
+    }
+
+    let fixtures = load_fixtures(dir)?;
+    let (faces, conversion) = convert(&data_file.faces, &fixtures, stored, config.hash_strategy);
+    println!(
+        "Converted {} faces from {} to {}, dropped {} without an image",
+        conversion.converted, stored, config.hash_strategy, conversion.dropped
+    );
+    rotate_backups(path, config.backups)?;
+    save_data_file(
+        path,
+        config.hash_strategy,
+        &faces,
+        should_compress(path, config.compress),
+        config.hex_keys,
+    )
+}
+
+fn create_player(args: &Args, config: &Config) -> Result<Player> {
+    let mut options = PlayerOptions::new(config.lucca_url.as_deref(), config.training)?;
+    options.learning_strategy = config.strategy;
+    options.skip_known = config.skip_known;
+    options.unknown_strategy = config.unknown_strategy;
+    options.fallback_value = config.fallback_value.clone();
+    options.name_format = config.name_format;
+    options.key_by = config.key_by;
+    options.hash_strategy = config.hash_strategy;
+    options.data_path = config.data_path.clone();
+    options.compress = config.compress;
+    options.hex_keys = config.hex_keys;
+    options.overrides_path = config.overrides_path.clone();
+    options.ca_cert = config.ca_cert.clone();
+    options.cookie_store = config.cookie_store;
+    options.persistent_login = config.persistent_login;
+    options.token_selector = config.token_selector.clone();
+    options.url_cache_path = config.url_cache_path.clone();
+    options.url_cache_ttl_hours = config.url_cache_ttl_hours;
+    options.max_game_retries = config.max_game_retries;
+    options.min_image_bytes = config.min_image_bytes;
+    options.image_timeout = config.image_timeout_ms.map(Duration::from_millis);
+    options.human_delay = config
+        .human_delay
+        .clone()
+        .map(|d| d.0)
+        .filter(|_| args.bench.is_none());
+    options.seed = config.seed;
+    options.encoding = config.encoding;
+    options.mock = is_mock(args);
+    options.image_sizes = args.image_sizes;
+    options.trace_bodies = args.trace_bodies;
+    options.min_confidence = config.min_confidence;
+    options.learn_threshold = config.learn_threshold;
+    options.keep_pending = args.keep_pending;
+    options.stats_path = config.stats_path.clone();
+    options.targets_path = config.targets_path.clone();
+    // Canned games would otherwise pollute the map, stats and URL cache
+    options.no_save = args.no_save || options.mock;
+    options.interactive = args.interactive && interactive::is_available();
+    if args.interactive && !options.interactive {
+        eprintln!("Not running in a terminal, unknown faces will be guessed");
+    }
+    options.backups = config.backups;
+    Player::new(options)
+}
+
+fn is_mock(args: &Args) -> bool {
+    let from_env = std::env::var("MOCK")
+        .is_ok_and(|v| !matches!(v.to_lowercase().as_str(), "" | "0" | "false" | "no"));
+    args.mock || args.bench.is_some() || from_env
+}
+
+/// Why updates of the map made by hand won't be saved, if they won't.
+fn unsaved_reason(args: &Args) -> Option<&'static str> {
+    match (args.no_save, is_mock(args)) {
+        (true, _) => Some("--no-save is set"),
+        (false, true) => Some("mock runs never save"),
+        (false, false) => None,
+    }
+}
+
+/// Saves an update of the map made by hand, telling when it is dropped
+/// instead.
+fn save_update(args: &Args, player: &Player) -> Result<()> {
+    if let Some(reason) = unsaved_reason(args) {
+        eprintln!("The update wasn't saved: {}", reason);
+    }
+    player.save_hash_map()
+}
+
+/// Plays `games` games without printing each answer, as `--bench` does.
+fn bench(player: &mut Player, games: u32) -> Result<Bench> {
+    let start = Instant::now();
+    let mut questions = 0;
+    for _ in 0..games {
+        let game = player.start_game()?;
+        let mut game_questions = player.questions(&game);
+        while let Some(context) = game_questions.next() {
+            game_questions.guess(context?)?;
+            questions += 1;
+        }
+    }
+    Ok(Bench {
+        games,
+        questions,
+        elapsed: start.elapsed(),
+    })
+}
 
-    let username = std::env::var("LUCCA_EMAIL").unwrap();
-    let password = std::env::var("LUCCA_PASSWORD").unwrap();
-    let lucca_url = std::env::var("LUCCA_URL").unwrap();
+fn run(args: &Args) -> Result<()> {
+    let config = load_config(args).map_err(PlayerError::Config)?;
+    if args.print_config {
+        print!("{}", toml::to_string(&config)?);
+        return Ok(());
+    }
+
+    // Salted once, so that a person gets the same alias in every report
+    let anonymizer = args.anonymize.then(Anonymizer::new);
+    let anonymizer = anonymizer.as_ref();
+    if let Some(dir) = &args.evaluate {
+        return run_evaluation(args, &config, dir, anonymizer);
+    }
+    if let Some(dir) = &args.convert_strategy {
+        return run_conversion(&config, dir);
+    }
+    if let (Some(dir), Some(path)) = (&args.deck, &args.deck_out) {
+        let html = deck::render_deck(&load_fixtures(dir)?);
+        return write_atomic(path, html.as_bytes());
+    }
+    if let [a, b] = args.diff.as_slice() {
+        let mut diff = Player::diff(a, b)?;
+        if let Some(anonymizer) = anonymizer {
+            diff = diff.anonymized(anonymizer);
+        }
+        match args.format {
+            Format::Text => println!("{}", diff),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+        }
+        return Ok(());
+    }
+
+    // Errors already telling what is wrong with the map are kept as they are
+    let mut player = create_player(args, &config).map_err(|e| match e.is::<PlayerError>() {
+        true => e,
+        false => PlayerError::Config(e).into(),
+    })?;
 
-    let learning = std::env::var("LUCCA_LEARNING").ok().is_some();
-    if learning {
+    if let [hash, name] = args.fix.as_slice() {
+        let hash = parse_hash(hash).map_err(|e| anyhow!(e))?;
+        let previous = player.correct(hash, name.clone())?;
+        println!("{}: {} -> {}", DisplayHash(hash), previous, name);
+        return save_update(args, &player);
+    }
+    if let Some(hash) = args.remove {
+        let previous = player.remove(hash)?;
+        println!("{}: removed {}", DisplayHash(hash), previous);
+        return save_update(args, &player);
+    }
+    if let Some(name) = &args.forget {
+        let forgotten = player.forget_name(name);
+        println!("{}: forgot {} faces", name, forgotten);
+        return save_update(args, &player);
+    }
+
+    if let Some(n) = args.restore_backup {
+        player.restore_backup(n)?;
+        println!("Restored backup {}, {} faces", n, player.known_faces());
+        return Ok(());
+    }
+
+    if let Some(hash) = args.nearest {
+        let max_distance = args.max_distance.unwrap_or(64);
+        match player.nearest(hash, max_distance) {
+            Some((nearest, name, distance)) => {
+                println!(
+                    "{}: {} ({} bits away)",
+                    DisplayHash(nearest),
+                    name,
+                    distance
+                )
+            }
+            None => println!(
+                "No face within {} bits of {}",
+                max_distance,
+                DisplayHash(hash)
+            ),
+        }
+        return Ok(());
+    }
+    if args.learning_curve {
+        if config.stats_path.is_none() {
+            return Err(
+                PlayerError::Config(anyhow!("The learning curve needs a stats file")).into(),
+            );
+        }
+        let mut report = player.learning_curve();
+        if let Some(anonymizer) = anonymizer {
+            report = report.anonymized(anonymizer);
+        }
+        match args.format {
+            Format::Text => println!("{}", report),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        }
+        return Ok(());
+    }
+    if let Some(n) = args.top_missed {
+        if config.stats_path.is_none() {
+            return Err(PlayerError::Config(anyhow!("--top-missed needs a stats file")).into());
+        }
+        let mut faces = player.top_missed(n, args.min_seen);
+        if let Some(anonymizer) = anonymizer {
+            faces = faces
+                .into_iter()
+                .map(|f| f.anonymized(anonymizer))
+                .collect();
+        }
+        match args.format {
+            Format::Text => {
+                println!("{:<32} {:^11} {:>4}  Hash", "Name", "Correct", "");
+                for face in &faces {
+                    println!("{}", face);
+                }
+            }
+            Format::Json => println!("{}", serde_json::to_string_pretty(&faces)?),
+        }
+        return Ok(());
+    }
+    if let Some(questions) = args.estimate {
+        if config.stats_path.is_none() {
+            return Err(PlayerError::Config(anyhow!("--estimate needs a stats file")).into());
+        }
+        let estimate = player.estimate_score(questions);
+        match args.format {
+            Format::Text => println!("{}", estimate),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&estimate)?),
+        }
+        return Ok(());
+    }
+    if args.coverage {
+        let report = player.coverage(args.directory_size);
+        match args.format {
+            Format::Text => println!("{}", report),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        }
+        return Ok(());
+    }
+    if args.analyze {
+        let mut analysis = player.analyze_map();
+        if let Some(anonymizer) = anonymizer {
+            analysis = analysis.anonymized(anonymizer);
+        }
+        match args.format {
+            Format::Text => println!("{}", analysis),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&analysis)?),
+        }
+        return Ok(());
+    }
+
+    let (username, password) = match is_mock(args) {
+        true => ("mock", "mock"),
+        false => {
+            let (_, username, password) = config.credentials().map_err(PlayerError::Config)?;
+            (username, password)
+        }
+    };
+    if args.dry_login {
+        player.login(username, password)?;
+        println!("Logged in as {}", username);
+        if args.logout {
+            if let Err(e) = player.logout() {
+                eprintln!("Failed to log out: {}", e);
+            }
+        }
+        return Ok(());
+    }
+    if config.training {
         println!("Starting in learning mode");
+    } else if player.known_faces() == 0 {
+        eprintln!(
+            "WARNING: no face has been learned yet, every answer will be a blind guess. \
+            Play a training game first (--training), or pass --allow-empty to play anyway."
+        );
+        if !args.allow_empty {
+            return Err(PlayerError::Config(anyhow!("Refusing to play with an empty map")).into());
+        }
+    }
+    player.login(username, password)?;
+
+    if args.accuracy_only {
+        let report = player.measure_accuracy()?;
+        match args.format {
+            Format::Text => println!("{}", report),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        }
+        return Ok(());
+    }
+
+    if let Some(games) = args.bench {
+        println!("{}", bench(&mut player, games)?);
+        if args.profile {
+            println!("{}", player.timings());
+        }
+        return Ok(());
     }
 
-    let mut player = Player::new(&lucca_url, learning).unwrap();
-    player.login(&username, &password).unwrap();
+    // Training games answer unknown faces on purpose, only ranked ones tell
+    // how good the map is
+    let min_accuracy = args.min_accuracy.filter(|_| !config.training);
+    let games = args.games.unwrap_or(1);
+    let warmup_games = args.warmup_games.unwrap_or(0);
+    for game in 1..=warmup_games {
+        let summary = player.play_game()?;
+        player.save_hash_map()?;
+        println!(
+            "Warmup game {} of {}: {} correct of {}",
+            game,
+            warmup_games,
+            summary.correct,
+            summary.scores.len()
+        );
+    }
+    let mut run = RunSummary::new(warmup_games);
+    let mut low_accuracy = None;
+    for game in 1..=games {
+        let summary = play_reported_game(args, &mut player, anonymizer, game, games)?;
+        player.save_hash_map()?;
+        run.record(&summary);
+        #[cfg(feature = "statsd")]
+        if let Some(address) = &config.statsd {
+            if let Err(e) = statsd::send_metrics(address, &summary) {
+                eprintln!("Failed to send the metrics to {}: {}", address, e);
+            }
+        }
 
-    let game = player.start_game().unwrap();
-    let mut scores = vec![];
-    for i in 0..game.nb_questions {
-        let score = player.guess(&game).unwrap();
-        scores.push(score);
-        println!("Scored {} at question {}", score, i + 1);
+        let accuracy = summary.accuracy();
+        if let Some(min) = min_accuracy.filter(|&min| accuracy < min) {
+            let missed: Vec<String> = summary
+                .missed
+                .iter()
+                .map(|m| {
+                    let name = m.name.as_deref().unwrap_or("unknown");
+                    match m.hash {
+                        Some(hash) => format!("{} ({})", name, DisplayHash(hash)),
+                        None => format!("{} (no image)", name),
+                    }
+                })
+                .collect();
+            eprintln!("Missed: {}", missed.join(", "));
+            low_accuracy = Some(PlayerError::LowAccuracy { accuracy, min });
+            break;
+        }
+        if args.stop_when_fully_known && summary.fully_known && game < games {
+            println!("Stopping after {} of {} games", game, games);
+            break;
+        }
     }
 
-    println!("Total score: {}", scores.iter().sum::<i32>());
+    if games > 1 {
+        let report = match args.format {
+            Format::Text => run.to_string(),
+            Format::Json => serde_json::to_string_pretty(&run)?,
+        };
+        match &args.summary_out {
+            Some(path) => write_atomic(path, report.as_bytes())?,
+            None => println!("{}", report),
+        }
+    }
 
-    player.save_hash_map().unwrap();
+    // The games are over, failing to log out doesn't undo them
+    if args.logout {
+        if let Err(e) = player.logout() {
+            eprintln!("Failed to log out: {}", e);
+        }
+    }
+
+    if args.profile {
+        println!("{}", player.timings());
+    }
+
+    match low_accuracy {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
+/// Where the report of game `game` of `games` is written: `path` itself for a
+/// single game, else `path` numbered like `summary-2.json`.
+fn game_path(path: &Path, game: u32, games: u32) -> PathBuf {
+    if games == 1 {
+        return path.to_owned();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, game, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, game),
+    };
+    path.with_file_name(name)
+}
+
+/// Plays game `game` of `games` and reports it as asked, with its summary and
+/// confusion matrix.
+fn play_reported_game(
+    args: &Args,
+    player: &mut Player,
+    anonymizer: Option<&Anonymizer>,
+    game: u32,
+    games: u32,
+) -> Result<GameSummary> {
+    let mut summary = player.play_game()?;
+    if let Some(anonymizer) = anonymizer {
+        summary = summary.anonymized(anonymizer);
+    }
+    if let Some(path) = &args.confusion_out {
+        let csv = summary.confusion.to_csv(args.confusion_long);
+        write_atomic(&game_path(path, game, games), csv.as_bytes())?;
+    }
+    let report = match args.format {
+        Format::Text => summary.to_string(),
+        Format::Json => serde_json::to_string_pretty(&summary)?,
+    };
+    match &args.summary_out {
+        Some(path) => write_atomic(&game_path(path, game, games), report.as_bytes())?,
+        None => println!("{}", report),
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_the_reports_of_several_games() {
+        let path = Path::new("out/summary.json");
+        assert_eq!(game_path(path, 1, 1), path);
+        assert_eq!(game_path(path, 2, 3), Path::new("out/summary-2.json"));
+        assert_eq!(
+            game_path(Path::new("summary"), 1, 2),
+            Path::new("summary-1")
+        );
+    }
+
+    #[test]
+    fn reports_every_game_of_a_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let summary_out = dir.path().join("summary.json");
+        let confusion_out = dir.path().join("confusion.csv");
+        let data_path = dir.path().join("data.ron");
+        let args = Args::parse_from([
+            "lucca_faces_autoplay_v2",
+            "--mock",
+            "--games",
+            "2",
+            "--format",
+            "json",
+            "--data",
+            data_path.to_str().unwrap(),
+            "--summary-out",
+            summary_out.to_str().unwrap(),
+            "--confusion-out",
+            confusion_out.to_str().unwrap(),
+        ]);
+        let config = load_config(&args).unwrap();
+        let mut player = create_player(&args, &config).unwrap();
+        for game in 1..=2 {
+            play_reported_game(&args, &mut player, None, game, 2).unwrap();
+        }
+
+        assert!(!summary_out.exists() && !confusion_out.exists());
+        for game in 1..=2 {
+            let summary = std::fs::read_to_string(game_path(&summary_out, game, 2)).unwrap();
+            assert!(serde_json::from_str::<serde_json::Value>(&summary).is_ok());
+            let confusion = std::fs::read_to_string(game_path(&confusion_out, game, 2)).unwrap();
+            assert!(!confusion.is_empty());
+        }
+    }
+
+    #[test]
+    fn tells_why_updates_are_not_saved() {
+        let args =
+            |flags: &[&str]| Args::parse_from(["lucca_faces_autoplay_v2"].iter().chain(flags));
+        // Unless MOCK is set in the environment
+        if std::env::var_os("MOCK").is_none() {
+            assert_eq!(unsaved_reason(&args(&["--forget", "Alice Martin"])), None);
+        }
+        let mock = args(&["--mock", "--forget", "Alice Martin"]);
+        assert_eq!(unsaved_reason(&mock), Some("mock runs never save"));
+        let no_save = args(&["--mock", "--no-save", "--remove", "1"]);
+        assert_eq!(unsaved_reason(&no_save), Some("--no-save is set"));
+    }
 }
@@ -0,0 +1,123 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::player::Suggestion;
+
+/// How loosely learned names are compared to suggestions, for accounts whose
+/// locale changes how names are displayed.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameFormat {
+    /// Names must be identical
+    #[default]
+    Exact,
+    /// Ignore the case, "DUPONT Jean" matches "Dupont Jean"
+    CaseInsensitive,
+    /// Ignore the case and the order of the words, "DUPONT Jean" matches
+    /// "Jean Dupont"
+    AnyOrder,
+    /// Ignore the case, accents, hyphens and extra spaces, "Jean-Pierre
+    /// Lévêque" matches "jean pierre  leveque"
+    Fuzzy,
+}
+
+/// Compares learned names to suggestions. The variants of `NameFormat` are
+/// the built-in matchers.
+pub trait NameMatcher: Send + Sync {
+    /// Canonical form of `name`, two names match when their forms are equal.
+    fn normalize(&self, name: &str) -> String;
+
+    fn matches(&self, stored: &str, suggestion: &str) -> bool {
+        self.normalize(stored) == self.normalize(suggestion)
+    }
+}
+
+impl NameMatcher for NameFormat {
+    fn normalize(&self, name: &str) -> String {
+        match self {
+            NameFormat::Exact => name.to_owned(),
+            NameFormat::CaseInsensitive => name.to_lowercase(),
+            NameFormat::AnyOrder => {
+                let lowercase = name.to_lowercase();
+                let mut words: Vec<&str> = lowercase.split_whitespace().collect();
+                words.sort_unstable();
+                words.join(" ")
+            }
+            NameFormat::Fuzzy => name
+                .nfd()
+                .filter(|c| !is_combining_mark(*c))
+                .map(|c| if c == '-' { ' ' } else { c })
+                .collect::<String>()
+                .to_lowercase()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    fn matches(&self, stored: &str, suggestion: &str) -> bool {
+        match self {
+            NameFormat::Exact => stored == suggestion,
+            _ => self.normalize(stored) == self.normalize(suggestion),
+        }
+    }
+}
+
+/// What faces are mapped to. Tenants whose display names change can key by
+/// the suggestion id instead, as long as it identifies the same person across
+/// games.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyBy {
+    /// The displayed name, `value` in the API
+    #[default]
+    Name,
+    /// The suggestion `id`, stored as a string
+    SuggestionId,
+}
+
+impl KeyBy {
+    /// The key `suggestion` is learned under.
+    pub fn key(&self, suggestion: &Suggestion) -> String {
+        match self {
+            KeyBy::Name => suggestion.value.clone(),
+            KeyBy::SuggestionId => suggestion.id.to_string(),
+        }
+    }
+
+    /// Whether `suggestion` is the one learned under `key`. Ids are compared
+    /// exactly, names according to `matcher`.
+    pub fn matches(&self, suggestion: &Suggestion, key: &str, matcher: &dyn NameMatcher) -> bool {
+        match self {
+            KeyBy::Name => matcher.matches(key, &suggestion.value),
+            KeyBy::SuggestionId => suggestion.id.to_string() == key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_names_displayed_in_another_order() {
+        assert!(NameFormat::AnyOrder.matches("DUPONT Jean", "Jean Dupont"));
+        assert!(NameFormat::AnyOrder.matches("Dupont Jean", "Jean Dupont"));
+        assert!(!NameFormat::AnyOrder.matches("Dupont Jean", "Jean Durand"));
+        assert!(NameFormat::CaseInsensitive.matches("DUPONT Jean", "Dupont Jean"));
+        assert!(!NameFormat::CaseInsensitive.matches("Dupont Jean", "Jean Dupont"));
+        assert!(!NameFormat::Exact.matches("DUPONT Jean", "Dupont Jean"));
+    }
+
+    #[test]
+    fn matches_names_fuzzily() {
+        let fuzzy = NameFormat::Fuzzy;
+        assert!(fuzzy.matches("Jean-Pierre Martin", "Jean Pierre Martin"));
+        assert!(fuzzy.matches("Hélène Lévêque", "Helene Leveque"));
+        assert!(fuzzy.matches("  Jean   Pierre\tMartin ", "Jean Pierre Martin"));
+        assert!(fuzzy.matches("JEAN-PIERRE LÉVÊQUE", "jean pierre leveque"));
+        assert!(!fuzzy.matches("Jean-Pierre Martin", "Jean Martin"));
+        assert!(!fuzzy.matches("Martin Jean-Pierre", "Jean-Pierre Martin"));
+    }
+}
@@ -1,151 +1,904 @@
-use std::io::Write;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Debug,
-    fs::{read_to_string, File},
-    path::Path,
+    io::{stdout, IsTerminal},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
 };
 
-use ahash::RandomState;
 use anyhow::{anyhow, Result};
-
+use base64::prelude::{Engine, BASE64_STANDARD};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::debug;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    header::{CONTENT_LENGTH, ETAG, LAST_MODIFIED},
+    Certificate, Method, StatusCode,
+};
 use scraper::{Html, Selector};
 
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 use url::Url;
 
+use crate::{
+    analysis::{self, MapAnalysis, MapDiff},
+    data_file::{self, save_data_file, DataFile, Votes},
+    encoding::BodyEncoding,
+    error::PlayerError,
+    hashing::{combine_hashes, hash_headers, hash_image, DisplayHash, HashStrategy, IMAGE_BYTES},
+    interactive,
+    names::{KeyBy, NameFormat, NameMatcher},
+    stats::{self, FaceRecord, FaceStats, LearningCurveReport, ScoreEstimate},
+    storage::{backup_path, rotate_backups, should_compress, write_atomic},
+    strategy::{choose_suggestion, LearningStrategy, UnknownStrategy},
+    summary::{AccuracyReport, CoverageReport, GameSummary, ImageSizes, MapStats, TargetCoverage},
+    timings::Timings,
+    transport::{HttpTransport, MockTransport, TracingTransport, Transport, MOCK_URL},
+    url_cache::UrlCache,
+};
+
 pub struct PlayerOptions {
-    lucca_url: Url,
-    training: bool,
+    /// Only needed to play, the map can be maintained offline
+    pub lucca_url: Option<Url>,
+    pub training: bool,
+    pub learning_strategy: LearningStrategy,
+    /// Only spend effort on unknown faces, requires `MaximizeCoverage`
+    pub skip_known: bool,
+    pub unknown_strategy: UnknownStrategy,
+    /// Suggestion answered to the faces that aren't in the map, when the
+    /// question offers it
+    pub fallback_value: Option<String>,
+    pub name_format: NameFormat,
+    /// What learned faces are mapped to
+    pub key_by: KeyBy,
+    pub hash_strategy: HashStrategy,
+    pub data_path: PathBuf,
+    /// Gzip the data file, which is also done when its extension is `.gz`
+    pub compress: bool,
+    /// Write the keys of the data file as hexadecimal strings
+    pub hex_keys: bool,
+    /// File of answers pinned by hand, in the same format as the data file
+    pub overrides_path: Option<PathBuf>,
+    /// PEM certificate trusted in addition to the system roots, for
+    /// networks whose TLS is terminated by an appliance with a private CA
+    pub ca_cert: Option<PathBuf>,
+    /// Keep the cookies between requests, which the login form relies on to
+    /// log the game requests in. Only worth disabling behind a gateway that
+    /// authenticates the requests itself
+    pub cookie_store: bool,
+    /// Ask Lucca for a long-lived session cookie, rather than one lasting
+    /// until the browser closes
+    pub persistent_login: bool,
+    /// Log the bodies of the requests and responses at trace level, their
+    /// passwords and tokens redacted. They still hold the names of the
+    /// colleagues
+    pub trace_bodies: bool,
+    /// CSS selector of the input holding the verification token of the
+    /// login form, its `value` being sent back
+    pub token_selector: String,
+    /// File remembering the hash of each image URL across runs
+    pub url_cache_path: Option<PathBuf>,
+    /// Age after which a cached URL is downloaded again
+    pub url_cache_ttl_hours: u64,
+    /// Timeout of the image downloads, shorter than the one of the API
+    /// calls so that a slow image doesn't hold up the game
+    pub image_timeout: Option<Duration>,
+    /// Size under which an image is a placeholder, e.g. a tracking pixel,
+    /// whose hash says nothing about the face
+    pub min_image_bytes: u64,
+    /// Think time waited before each answer, drawn within the range
+    pub human_delay: Option<Range<Duration>>,
+    /// Seed of the think times, drawn from the system when unset
+    pub seed: Option<u64>,
+    /// Number of times a game failing midway is abandoned for a new one
+    pub max_game_retries: usize,
+    /// Share of correct answers under which `MaximizeScore` considers a
+    /// known face uncertain
+    pub min_confidence: f64,
+    /// Votes the most voted name of a face needs before the face is
+    /// answered with it, the faces with fewer being pending
+    pub learn_threshold: u32,
+    /// Save the pending faces too, for their votes to add up across runs
+    pub keep_pending: bool,
+    /// Report the sizes of the images downloaded in the summary
+    pub image_sizes: bool,
+    /// Play canned games instead of connecting to Lucca
+    pub mock: bool,
+    /// How the bodies of the game requests are encoded
+    pub encoding: BodyEncoding,
+    /// File keeping every sighting of each face across runs
+    pub stats_path: Option<PathBuf>,
+    /// File listing the names to learn, one per line, the other faces being
+    /// ignored
+    pub targets_path: Option<PathBuf>,
+    /// Keep what is learned in memory only, the data file is never written
+    pub no_save: bool,
+    /// Ask which suggestion unknown faces are instead of guessing
+    pub interactive: bool,
+    /// Number of previous versions of the data file kept on save
+    pub backups: usize,
+}
+
+impl PlayerOptions {
+    pub fn new(lucca_url: Option<&str>, training: bool) -> Result<Self> {
+        Ok(Self {
+            lucca_url: lucca_url.map(Url::parse).transpose()?,
+            training,
+            learning_strategy: LearningStrategy::default(),
+            skip_known: false,
+            unknown_strategy: UnknownStrategy::default(),
+            fallback_value: None,
+            name_format: NameFormat::default(),
+            key_by: KeyBy::default(),
+            hash_strategy: HashStrategy::default(),
+            data_path: PathBuf::from(HASH_FILE_PATH),
+            compress: false,
+            hex_keys: false,
+            overrides_path: None,
+            ca_cert: None,
+            url_cache_path: None,
+            url_cache_ttl_hours: DEFAULT_URL_CACHE_TTL_HOURS,
+            cookie_store: true,
+            persistent_login: true,
+            trace_bodies: false,
+            token_selector: DEFAULT_TOKEN_SELECTOR.to_owned(),
+            image_timeout: None,
+            human_delay: None,
+            seed: None,
+            min_image_bytes: 0,
+            max_game_retries: 0,
+            min_confidence: DEFAULT_MIN_CONFIDENCE,
+            learn_threshold: 1,
+            keep_pending: false,
+            image_sizes: false,
+            mock: false,
+            encoding: BodyEncoding::default(),
+            stats_path: None,
+            targets_path: None,
+            no_save: false,
+            interactive: false,
+            backups: 0,
+        })
+    }
 }
 
 pub struct Player {
-    client: reqwest::blocking::Client,
+    /// Only builds the requests, they are sent through `transport`
+    client: Client,
+    /// Whether `client` was built from the options, rather than handed to
+    /// `with_client`, so that it can be rebuilt
+    own_client: bool,
+    transport: Box<dyn Transport>,
     options: PlayerOptions,
+    /// Compares learned names to suggestions, from `name_format`
+    name_matcher: Box<dyn NameMatcher>,
+    /// Most voted name of each face of `votes`
     hash_map: HashMap<u64, String>,
+    votes: HashMap<u64, Votes>,
+    /// Answers pinned by hand, they take precedence over `hash_map` and are
+    /// never learned over
+    overrides: HashMap<u64, String>,
+    url_cache: UrlCache,
+    /// Names to learn, the others being ignored, when set
+    targets: Option<Vec<String>>,
+    stats: FaceStats,
+    /// Sizes of the images downloaded, in bytes
+    image_sizes: Vec<u64>,
+    /// Parsed from `token_selector`, once so that an invalid one fails early
+    token_selector: Selector,
+    rng: StdRng,
+    /// Seed of `rng`, drawn when none is configured, so that a run can be
+    /// replayed with it
+    seed: u64,
+    timings: Timings,
 }
 
+/// Like the other API payloads, fields are accepted both in the camelCase
+/// Lucca sends and in snake_case, which some API versions use.
 #[derive(Deserialize, Debug)]
 pub struct Game {
     id: String,
-    #[serde(rename = "nbQuestions")]
+    #[serde(rename = "nbQuestions", alias = "nb_questions")]
     pub nb_questions: u32,
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(try_from = "RawQuestion")]
 struct Question {
     id: u32,
-    #[serde(rename = "imageUrl")]
-    image_url: String,
-    suggestions: [Suggestion; 4],
+    image_urls: Vec<String>,
+    suggestions: Vec<Suggestion>,
 }
 
-#[derive(Deserialize, Debug)]
-struct Suggestion {
+impl Question {
+    fn num_suggestions(&self) -> usize {
+        self.suggestions.len()
+    }
+}
+
+/// Questions either come with a single `imageUrl` or, for some game
+/// variants, several `imageUrls` of the same person.
+#[derive(Deserialize)]
+struct RawQuestion {
     id: u32,
-    value: String,
+    #[serde(rename = "imageUrl", alias = "image_url")]
+    image_url: Option<String>,
+    #[serde(rename = "imageUrls", alias = "image_urls", default)]
+    image_urls: Vec<String>,
+    #[serde(deserialize_with = "deserialize_suggestions")]
+    suggestions: Vec<Suggestion>,
+}
+
+impl TryFrom<RawQuestion> for Question {
+    type Error = String;
+
+    fn try_from(raw: RawQuestion) -> Result<Self, Self::Error> {
+        let mut image_urls = raw.image_urls;
+        if let Some(image_url) = raw.image_url {
+            if !image_urls.contains(&image_url) {
+                image_urls.insert(0, image_url);
+            }
+        }
+        if image_urls.is_empty() {
+            return Err(format!("question {} has no image", raw.id));
+        }
+
+        Ok(Self {
+            id: raw.id,
+            image_urls,
+            suggestions: raw.suggestions,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Suggestion {
+    pub id: u32,
+    /// Empty when the API sends none, the suggestion then matching no name
+    #[serde(default, deserialize_with = "deserialize_name")]
+    pub value: String,
+}
+
+/// Decodes the HTML entities some names are sent with, as in `Jo&#235;l`,
+/// so that they are stored and compared as displayed. A null name is empty,
+/// like a missing one.
+fn deserialize_name<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+    Ok(html_escape::decode_html_entities(&name).into_owned())
+}
+
+/// Rejects questions without suggestions or holding several suggestions with
+/// the same id, as the answer would then be ambiguous. Games usually offer 4
+/// suggestions, but some variants offer more or fewer.
+fn deserialize_suggestions<'de, D>(deserializer: D) -> Result<Vec<Suggestion>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let suggestions = Vec::<Suggestion>::deserialize(deserializer)?;
+    if suggestions.is_empty() {
+        return Err(serde::de::Error::custom("no suggestions"));
+    }
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        if suggestions[..i].iter().any(|s| s.id == suggestion.id) {
+            return Err(serde::de::Error::custom(format!(
+                "duplicate suggestion id {}",
+                suggestion.id
+            )));
+        }
+    }
+    Ok(suggestions)
 }
 
 #[derive(Deserialize, Debug)]
 struct GuessResponse {
     score: i32,
-    #[serde(rename = "isCorrect")]
+    #[serde(rename = "isCorrect", alias = "is_correct")]
     is_correct: bool,
-    #[serde(rename = "correctSuggestionId")]
+    #[serde(rename = "correctSuggestionId", alias = "correct_suggestion_id")]
     correct_suggestion_id: u32,
 }
 
+/// Final result of a game, as computed by the server.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct GameResult {
+    #[serde(rename(deserialize = "totalScore"), alias = "total_score")]
+    pub total_score: i32,
+    pub rank: Option<u32>,
+    pub percentile: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct GuessOutcome {
+    pub score: i32,
+    pub is_correct: bool,
+    pub new_face: bool,
+    pub image_failure: bool,
+    /// The image was smaller than `min_image_bytes`, and wasn't hashed
+    pub undersized_image: bool,
+    pub image_hash: Option<u64>,
+    /// Name of the correct suggestion, unset when the server named one the
+    /// question doesn't offer
+    pub correct_name: Option<String>,
+    /// Name of the suggestion answered
+    pub guessed_name: String,
+    /// Number of suggestions the question offered
+    pub suggestions: usize,
+}
+
+/// A question of a game, whose images have been fetched and looked up but
+/// which hasn't been answered yet.
+pub struct QuestionContext {
+    question: Question,
+    image_hash: Option<u64>,
+    undersized_image: bool,
+    known_name: Option<String>,
+}
+
+impl QuestionContext {
+    pub fn id(&self) -> u32 {
+        self.question.id
+    }
+
+    pub fn suggestions(&self) -> &[Suggestion] {
+        &self.question.suggestions
+    }
+
+    pub fn num_suggestions(&self) -> usize {
+        self.question.num_suggestions()
+    }
+
+    /// Key of the images, unset when they couldn't be fetched
+    pub fn image_hash(&self) -> Option<u64> {
+        self.image_hash
+    }
+
+    /// Key the face is known under, from the overrides or the learned map
+    pub fn known_name(&self) -> Option<&str> {
+        self.known_name.as_deref()
+    }
+
+    fn is_new_face(&self) -> bool {
+        self.image_hash.is_some() && self.known_name.is_none()
+    }
+}
+
+/// The questions of a game, in order, see `Player::questions`. Each question
+/// has to be answered, with `answer` or `guess`, before the next one is
+/// yielded, the game expecting alternating `questions/next` and `guess`
+/// calls.
+pub struct Questions<'a> {
+    player: &'a mut Player,
+    game: &'a Game,
+    asked: u32,
+    answered: u32,
+    /// Whether the answers are learned and recorded in the stats
+    learn: bool,
+}
+
+impl Questions<'_> {
+    /// Answers the question of `context` with the suggestion `suggestion_id`,
+    /// and learns the correct one.
+    pub fn answer(&mut self, context: QuestionContext, suggestion_id: u32) -> Result<GuessOutcome> {
+        let outcome = self
+            .player
+            .answer(self.game, context, suggestion_id, self.learn)?;
+        self.answered += 1;
+        Ok(outcome)
+    }
+
+    /// Answers the question of `context` the way the player is configured to.
+    pub fn guess(&mut self, context: QuestionContext) -> Result<GuessOutcome> {
+        let suggestion_id = self.player.suggest(&context)?.id;
+        self.answer(context, suggestion_id)
+    }
+}
+
+impl Iterator for Questions<'_> {
+    type Item = Result<QuestionContext>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.asked == self.game.nb_questions {
+            return None;
+        }
+        if self.answered < self.asked {
+            return Some(Err(anyhow!("Question {} hasn't been answered", self.asked)));
+        }
+
+        self.asked += 1;
+        let start = Instant::now();
+        let question = self.player.next_question(self.game);
+        self.player.timings.next_question += start.elapsed();
+        match question {
+            Ok(question) => Some(Ok(self.player.context(question))),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
 const LOGIN_ADDR: &str = "identity/login";
+const LOGOUT_ADDR: &str = "identity/logout";
 const FACES_ADDR: &str = "faces/api";
+pub const HASH_FILE_PATH: &str = "data";
+pub const DEFAULT_MIN_CONFIDENCE: f64 = 0.8;
+pub const DEFAULT_TOKEN_SELECTOR: &str = "input[name=\"__RequestVerificationToken\"]";
+/// A week, URLs are only reused for other images when pictures change
+pub const DEFAULT_URL_CACHE_TTL_HOURS: u64 = 24 * 7;
+/// Images may be embedded in the questions instead of linked
+const DATA_URI_PREFIX: &str = "data:";
+/// Number of times a rate limited request is sent again
+const RATE_LIMIT_RETRIES: usize = 3;
+/// Wait when a 429 doesn't say how long to, and longest wait accepted
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// The statuses a request accepts, any other failing it. The client follows
+/// redirects, so a 3xx only shows up when it couldn't be followed.
+#[derive(Clone, Copy, Debug)]
+enum Accepted {
+    /// 2xx statuses but 204, the body being read
+    Content,
+    /// Any 2xx status, 204 included, for requests whose body is ignored
+    Success,
+    /// Every status, the caller telling them apart
+    Any,
+}
+
+impl Accepted {
+    fn accepts(self, status: StatusCode) -> bool {
+        match self {
+            Accepted::Content => status.is_success() && status != StatusCode::NO_CONTENT,
+            Accepted::Success => status.is_success(),
+            Accepted::Any => true,
+        }
+    }
+}
+
+fn progress_bar(questions: u32) -> ProgressBar {
+    let style = ProgressStyle::with_template("{bar:40} {pos}/{len} questions, {msg}").unwrap();
+    ProgressBar::with_draw_target(
+        Some(questions.into()),
+        indicatif::ProgressDrawTarget::stdout(),
+    )
+    .with_style(style)
+}
+
+/// Reads a list of names, one per line, blank lines and lines starting with
+/// `#` being skipped.
+fn load_targets(path: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read the targets {}: {}", path.display(), e))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+fn build_client(options: &PlayerOptions) -> Result<Client> {
+    let mut builder = Client::builder().cookie_store(options.cookie_store);
+    if let Some(path) = &options.ca_cert {
+        builder = builder.add_root_certificate(load_certificate(path)?);
+    }
+    Ok(builder.build()?)
+}
+
+fn build_transport(options: &PlayerOptions, client: &Client) -> Box<dyn Transport> {
+    let transport: Box<dyn Transport> = match options.mock {
+        true => Box::new(MockTransport::default()),
+        false => Box::new(HttpTransport(client.clone())),
+    };
+    match options.trace_bodies {
+        true => Box::new(TracingTransport(transport)),
+        false => transport,
+    }
+}
+
+fn load_certificate(path: &Path) -> Result<Certificate> {
+    let pem = std::fs::read(path)
+        .map_err(|e| anyhow!("Failed to read the certificate {}: {}", path.display(), e))?;
+    Certificate::from_pem(&pem)
+        .map_err(|e| anyhow!("Invalid PEM certificate {}: {}", path.display(), e))
+}
 
-static HASHER: RandomState = RandomState::with_seeds(
-    10960905448801897020,
-    6565933669389301275,
-    5017652980937232669,
-    4134542598451985848,
-);
+pub fn load_hash_map(path: &Path) -> Result<HashMap<u64, String>> {
+    Ok(data_file::names(&DataFile::load(path)?.faces))
+}
 
 impl Player {
-    pub fn new(lucca_url: &str, training: bool) -> Result<Self> {
-        let client = reqwest::blocking::Client::builder()
-            .cookie_store(true)
-            .build()?;
+    pub fn new(options: PlayerOptions) -> Result<Self> {
+        let client = build_client(&options)?;
+        let mut player = Self::with_client(client, options)?;
+        player.own_client = true;
+        Ok(player)
+    }
 
-        let options = PlayerOptions {
-            lucca_url: Url::parse(lucca_url)?,
-            training,
-        };
+    /// A player sending its requests with `client`, for settings the
+    /// options don't cover. `ca_cert` and `cookie_store` are then left to the
+    /// client, and `logout` can't clear its cookies.
+    pub fn with_client(client: Client, mut options: PlayerOptions) -> Result<Self> {
+        if options.learning_strategy == LearningStrategy::MaximizeCoverage && !options.training {
+            return Err(anyhow!(
+                "The maximize-coverage strategy is only available in training mode"
+            ));
+        }
+        if options.targets_path.is_some() && options.key_by != KeyBy::Name {
+            return Err(anyhow!(
+                "Targets are names and require keying faces by name"
+            ));
+        }
+        if options.skip_known && options.learning_strategy != LearningStrategy::MaximizeCoverage {
+            return Err(anyhow!(
+                "Skipping known faces requires the maximize-coverage strategy"
+            ));
+        }
 
-        let hash_file_path = "data";
-        let path = Path::new(hash_file_path);
-        let hash_map = match path.exists() {
-            true => ron::from_str(read_to_string(path)?.as_str())?,
-            false => HashMap::<u64, String>::new(),
-        };
+        if options.mock && options.lucca_url.is_none() {
+            options.lucca_url = Some(Url::parse(MOCK_URL)?);
+        }
+        let transport = build_transport(&options, &client);
 
-        Ok(Self {
+        let url_cache_ttl = Duration::from_secs(options.url_cache_ttl_hours * 3600);
+        let seed = options.seed.unwrap_or_else(rand::random);
+        let token_selector = Selector::parse(&options.token_selector).map_err(|e| {
+            anyhow!(
+                "Invalid verification token selector {}: {}",
+                options.token_selector,
+                e
+            )
+        })?;
+        let mut player = Self {
             client,
+            own_client: false,
+            transport,
+            name_matcher: Box::new(options.name_format),
             options,
-            hash_map,
-        })
+            hash_map: HashMap::new(),
+            votes: HashMap::new(),
+            overrides: HashMap::new(),
+            url_cache: UrlCache::new(url_cache_ttl),
+            targets: None,
+            stats: FaceStats::default(),
+            image_sizes: vec![],
+            token_selector,
+            rng: StdRng::seed_from_u64(seed),
+            seed,
+            timings: Timings::default(),
+        };
+        player.reload_hash_map()?;
+        debug!("Loaded a map of {}", player.map_stats());
+        if let Some(path) = &player.options.overrides_path {
+            if !path.exists() {
+                return Err(anyhow!("Overrides file {} not found", path.display()));
+            }
+            let overrides = DataFile::load(path)?.faces_for(player.options.hash_strategy)?;
+            player.overrides = data_file::names(&overrides);
+        }
+        // Without a file, the cache still spares downloading the faces
+        // repeated within the run
+        if let Some(path) = &player.options.url_cache_path {
+            player.url_cache = UrlCache::load(path, url_cache_ttl)?;
+        }
+        if let Some(path) = &player.options.targets_path {
+            player.targets = Some(load_targets(path)?);
+        }
+        if let Some(path) = &player.options.stats_path {
+            player.stats = FaceStats::load(path)?;
+        }
+
+        Ok(player)
     }
 
     pub fn reload_hash_map(&mut self) -> Result<()> {
-        let hash_file_path = "data";
-        let path = Path::new(hash_file_path);
-        self.hash_map = match path.exists() {
-            true => ron::from_str(read_to_string(path)?.as_str())?,
-            false => HashMap::<u64, String>::new(),
-        };
+        self.votes =
+            DataFile::load(&self.options.data_path)?.faces_for(self.options.hash_strategy)?;
+        self.hash_map = data_file::confirmed_names(&self.votes, self.options.learn_threshold);
 
         Ok(())
     }
 
+    pub fn timings(&self) -> &Timings {
+        &self.timings
+    }
+
+    /// Writes the map to the data file, unless saving is disabled.
     pub fn save_hash_map(&self) -> Result<()> {
-        let hash_file_path = "data";
-        let path = Path::new(hash_file_path);
+        if self.options.no_save {
+            return Ok(());
+        }
 
-        let mut file = File::create(path)?;
+        if let Some(path) = &self.options.url_cache_path {
+            self.url_cache.save(path)?;
+        }
+        if let Some(path) = &self.options.stats_path {
+            self.stats.save(path)?;
+        }
 
-        file.write_all(&ron::to_string(&self.hash_map)?.into_bytes())?;
-        Ok(())
+        // Faces are confirmed in a single run unless asked otherwise, so that
+        // one-off errors don't pile up in the file
+        let confirmed: HashMap<u64, Votes>;
+        let faces = match self.options.keep_pending {
+            true => &self.votes,
+            false => {
+                confirmed = self
+                    .votes
+                    .iter()
+                    .filter(|(hash, _)| self.hash_map.contains_key(hash))
+                    .map(|(&hash, votes)| (hash, votes.clone()))
+                    .collect();
+                &confirmed
+            }
+        };
+        let path = &self.options.data_path;
+        let compress = should_compress(path, self.options.compress);
+        rotate_backups(path, self.options.backups)?;
+        save_data_file(
+            path,
+            self.options.hash_strategy,
+            faces,
+            compress,
+            self.options.hex_keys,
+        )
     }
 
-    pub fn login(&self, username: &str, password: &str) -> Result<()> {
-        let login_url = self.options.lucca_url.join(&LOGIN_ADDR)?;
-        let response = self.client.get(login_url.clone()).send()?;
+    /// Replaces the data file with its `n`-th most recent backup, and loads
+    /// it. Unlike the atomic write, this recovers from wrong names having
+    /// been learned.
+    pub fn restore_backup(&mut self, n: usize) -> Result<()> {
+        let backup = backup_path(&self.options.data_path, n);
+        if !backup.exists() {
+            return Err(anyhow!("No backup found at {}", backup.display()));
+        }
+        write_atomic(&self.options.data_path, &std::fs::read(&backup)?)?;
+        self.reload_hash_map()
+    }
 
-        if !response.status().is_success() {
-            return Err(anyhow!(
-                "GET request to {} resulted in a code {}",
-                &login_url,
-                response.status()
-            ));
+    fn lucca_url(&self) -> Result<&Url> {
+        self.options
+            .lucca_url
+            .as_ref()
+            .ok_or(anyhow!("No Lucca URL configured"))
+    }
+
+    /// Compares the maps of two data files, which must have been learned
+    /// with the same strategy for their keys to be comparable.
+    pub fn diff(a: &Path, b: &Path) -> Result<MapDiff> {
+        let file_a = DataFile::load(a)?;
+        let file_b = DataFile::load(b)?;
+        if let (Some(strategy_a), Some(strategy_b)) = (file_a.strategy, file_b.strategy) {
+            if strategy_a != strategy_b {
+                return Err(anyhow!(
+                    "{} uses the {} hash strategy and {} the {} one, convert one of them first",
+                    a.display(),
+                    strategy_a,
+                    b.display(),
+                    strategy_b
+                ));
+            }
+        }
+        Ok(analysis::diff(
+            &data_file::names(&file_a.faces),
+            &data_file::names(&file_b.faces),
+        ))
+    }
+
+    /// Number of faces in the map.
+    pub fn known_faces(&self) -> usize {
+        self.hash_map.len()
+    }
+
+    /// Number of faces voted for fewer than `learn_threshold` times, which
+    /// aren't answered with yet, and only saved with `keep_pending`.
+    pub fn pending_faces(&self) -> usize {
+        self.votes
+            .keys()
+            .filter(|hash| !self.hash_map.contains_key(hash))
+            .count()
+    }
+
+    pub fn map_stats(&self) -> MapStats {
+        let names: HashSet<&String> = self.hash_map.values().collect();
+        MapStats {
+            entries: self.hash_map.len(),
+            unique_names: names.len(),
+            approx_bytes: self.hash_map.len() * size_of::<u64>()
+                + self.hash_map.values().map(String::len).sum::<usize>(),
+        }
+    }
+
+    /// How many names the map knows, out of the `total` people of the
+    /// directory when known. Names rather than faces are counted, as a
+    /// person may have been learned under several pictures.
+    pub fn coverage(&self, total: Option<usize>) -> CoverageReport {
+        let names: HashSet<&String> = self.hash_map.values().collect();
+        CoverageReport {
+            learned: names.len(),
+            total,
+        }
+    }
+
+    /// Finds the stored hash closest to `hash` in Hamming distance, if any is
+    /// within `max_distance` bits, along with its name and distance.
+    ///
+    /// The map is scanned linearly, which takes about 10µs for a few thousand
+    /// faces. Distances are only meaningful for hashes that keep similar
+    /// images close, which byte hashes don't.
+    pub fn nearest(&self, hash: u64, max_distance: u32) -> Option<(u64, String, u32)> {
+        self.hash_map
+            .iter()
+            .map(|(&h, name)| (h, name, (h ^ hash).count_ones()))
+            .filter(|&(_, _, distance)| distance <= max_distance)
+            .min_by_key(|&(_, _, distance)| distance)
+            .map(|(h, name, distance)| (h, name.clone(), distance))
+    }
+
+    fn is_target(&self, name: &str) -> bool {
+        self.targets
+            .as_ref()
+            .is_none_or(|targets| targets.iter().any(|t| self.name_matcher.matches(t, name)))
+    }
+
+    /// How many of the targets have been learned, when there are some.
+    pub fn target_coverage(&self) -> Option<TargetCoverage> {
+        let targets = self.targets.as_ref()?;
+        let missing: Vec<String> = targets
+            .iter()
+            .filter(|t| {
+                !self
+                    .hash_map
+                    .values()
+                    .any(|n| self.name_matcher.matches(t, n))
+            })
+            .cloned()
+            .collect();
+        Some(TargetCoverage {
+            targets: targets.len(),
+            learned: targets.len() - missing.len(),
+            missing,
+        })
+    }
+
+    /// How many sightings faces needed before being answered correctly
+    /// `LEARNED_STREAK` times in a row, from the stats file.
+    pub fn learning_curve(&self) -> LearningCurveReport {
+        stats::learning_curve(&self.stats, &self.hash_map)
+    }
+
+    /// The `n` faces most often answered wrongly, among those seen at least
+    /// `min_seen` times, from the stats file.
+    pub fn top_missed(&self, n: usize, min_seen: usize) -> Vec<FaceRecord> {
+        stats::top_missed(&self.stats, &self.hash_map, n, min_seen)
+    }
+
+    /// How many of `nb_questions` questions the map would answer correctly,
+    /// from the stats file.
+    pub fn estimate_score(&self, nb_questions: u32) -> ScoreEstimate {
+        stats::estimate(&self.stats, &self.hash_map, nb_questions)
+    }
+
+    /// Looks for people with several faces.
+    pub fn analyze_map(&self) -> MapAnalysis {
+        analysis::analyze(&self.hash_map)
+    }
+
+    /// Maps `hash` to `name` in place of the learned name, which is returned.
+    pub fn correct(&mut self, hash: u64, name: String) -> Result<String> {
+        let entry = self.hash_map.get_mut(&hash).ok_or(anyhow!(
+            "No face is stored under the hash {}",
+            DisplayHash(hash)
+        ))?;
+        // The votes were for a wrong name, they start over, the fix being
+        // confirmed at once
+        let mut votes = Votes::new(name.clone());
+        for _ in 1..self.options.learn_threshold {
+            votes.vote(&name);
+        }
+        self.votes.insert(hash, votes);
+        Ok(std::mem::replace(entry, name))
+    }
+
+    /// Forgets the face stored under `hash`, returning its name.
+    pub fn remove(&mut self, hash: u64) -> Result<String> {
+        let name = self.hash_map.remove(&hash).ok_or(anyhow!(
+            "No face is stored under the hash {}",
+            DisplayHash(hash)
+        ))?;
+        self.votes.remove(&hash);
+        Ok(name)
+    }
+
+    /// Forgets every face stored under `name`, compared the way answers are,
+    /// e.g. for someone who left. Returns the number of faces forgotten.
+    pub fn forget_name(&mut self, name: &str) -> usize {
+        let matches = |key: &String| match self.options.key_by {
+            KeyBy::Name => self.name_matcher.matches(key, name),
+            KeyBy::SuggestionId => key == name,
+        };
+        let forgotten: Vec<u64> = self
+            .hash_map
+            .iter()
+            .filter(|(_, key)| matches(key))
+            .map(|(&hash, _)| hash)
+            .collect();
+        for hash in &forgotten {
+            self.hash_map.remove(hash);
+            self.votes.remove(hash);
+        }
+        forgotten.len()
+    }
+
+    pub fn login(&mut self, username: &str, password: &str) -> Result<()> {
+        let start = Instant::now();
+        let result = self.submit_login(username, password);
+        self.timings.login += start.elapsed();
+        result
+    }
+
+    /// Ends the session on Lucca's side, then forgets its cookies whether
+    /// that succeeded or not, unless the client was given to `with_client`.
+    pub fn logout(&mut self) -> Result<()> {
+        let logout_url = self.lucca_url()?.join(LOGOUT_ADDR)?;
+        let response = self.send_logged(self.client.post(logout_url), Accepted::Success);
+        // reqwest's cookie store can't be cleared, a new client starts empty
+        if self.own_client {
+            self.client = build_client(&self.options)?;
+            self.transport = build_transport(&self.options, &self.client);
         }
 
+        response?;
+        Ok(())
+    }
+
+    fn submit_login(&self, username: &str, password: &str) -> Result<()> {
+        let login_url = self.lucca_url()?.join(LOGIN_ADDR)?;
+        let response = self.send_logged(self.client.get(login_url.clone()), Accepted::Content)?;
+
         let html = Html::parse_document(response.text()?.as_str());
-        let selector = Selector::parse("input[name=\"__RequestVerificationToken\"]").unwrap();
+        if let Some(challenge) = login_challenge(&html) {
+            return Err(challenge.into());
+        }
         let verification_token = html
-            .select(&selector)
+            .select(&self.token_selector)
             .next()
-            .ok_or(anyhow!("Failed to retrieve the verification token element"))?
+            .ok_or(PlayerError::Auth(
+                "Failed to retrieve the verification token element".to_owned(),
+            ))?
             .value()
             .attr("value")
-            .ok_or(anyhow!("Failed to retrieve the verification token"))?;
+            .ok_or(PlayerError::Auth(
+                "Failed to retrieve the verification token".to_owned(),
+            ))?;
 
         let mut login_form = HashMap::new();
         login_form.insert("ReturnUrl", "/home");
         login_form.insert("UserName", username);
         login_form.insert("Password", password);
-        login_form.insert("IsPersistent", "true");
+        let is_persistent = self.options.persistent_login.to_string();
+        login_form.insert("IsPersistent", is_persistent.as_str());
         login_form.insert("__RequestVerificationToken", verification_token);
-        let response = self.client.post(login_url).form(&login_form).send()?;
+        // A rejected login is an authentication error, not a failed request
+        let response =
+            self.send_logged(self.client.post(login_url).form(&login_form), Accepted::Any)?;
 
         if response.status().is_success() {
-            return Ok(());
+            // The form may be answered with a second step instead of the home
+            let html = Html::parse_document(response.text()?.as_str());
+            return match login_challenge(&html) {
+                Some(challenge) => Err(challenge.into()),
+                None => Ok(()),
+            };
         }
 
-        Err(anyhow!("Failed to log in"))
+        Err(PlayerError::Auth(format!(
+            "Failed to log in, got a code {}",
+            response.status()
+        ))
+        .into())
     }
 
     pub fn start_game(&self) -> Result<Game> {
@@ -158,75 +911,503 @@ impl Player {
         training_form.insert("departmentIds", Vec::<usize>::new());
         training_form.insert("establishmentIds", vec![]);
 
-        let game_url = self.options.lucca_url.join(&url_str)?;
+        let game_url = self.lucca_url()?.join(&url_str)?;
+        let encoding = self.options.encoding;
+        let request = self.client.post(game_url);
         let request = match self.options.training {
-            true => self.client.post(game_url).json(&training_form),
-            false => self
-                .client
-                .post(game_url)
-                .json(&HashMap::<String, String>::new()),
+            true => encoding.apply(request, &training_form)?,
+            false => encoding.apply(request, &HashMap::<String, String>::new())?,
         };
-        let response = request.send()?;
+        let response = self.send_logged(request, Accepted::Content)?;
+        let game: Game = read_json(response)?;
+        debug!("Game {} started, random seed {}", game.id, self.seed);
 
-        if !response.status().is_success() {
-            return Err(anyhow!(
-                "POST request to {} resulted in a code {}",
-                url_str,
-                response.status()
-            ));
+        Ok(game)
+    }
+
+    /// Plays a whole game, answering every question with `Questions::guess`.
+    ///
+    /// A game failing midway is abandoned for a new one, up to
+    /// `max_game_retries` times, the summary covering the answers of every
+    /// game. Failing to start a game isn't retried, as a new one would fail
+    /// the same way.
+    pub fn play_game(&mut self) -> Result<GameSummary> {
+        let mut summary = GameSummary::new(self.options.learning_strategy);
+        // Only the think times are random
+        summary.seed = self.options.human_delay.is_some().then_some(self.seed);
+        let game = loop {
+            let game = self.start_game()?;
+            match self.play_questions(&game, &mut summary) {
+                Ok(()) => break game,
+                Err(e) if summary.abandoned_games < self.options.max_game_retries => {
+                    eprintln!("The game failed, starting a new one: {}", e);
+                    summary.abandoned_games += 1;
+                    self.save_hash_map()?;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        summary.fully_known =
+            self.options.training && summary.new_faces == 0 && !summary.scores.is_empty();
+        summary.targets = self.target_coverage();
+        summary.map = self.map_stats();
+        summary.pending_faces = self.pending_faces();
+        if self.options.image_sizes {
+            summary.image_sizes = ImageSizes::new(&self.image_sizes);
         }
 
-        let game = response.json()?;
+        // Our own sum of the scores is still reported if this fails
+        summary.result = match self.finish_game(&game) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                eprintln!("Failed to retrieve the result of the game: {}", e);
+                None
+            }
+        };
 
-        Ok(game)
+        Ok(summary)
     }
 
-    pub fn guess(&mut self, game: &Game) -> Result<i32> {
-        let url_str = FACES_ADDR.to_owned() + "/games/" + game.id.as_str() + "/questions/next";
-        let next_url = self.options.lucca_url.join(&url_str)?;
-        let response = self
-            .client
-            .post(next_url)
-            .json(&HashMap::<String, String>::new())
-            .send()?;
-
-        let question: Question = response.json()?;
-
-        let url_str = self.options.lucca_url.join(&question.image_url)?;
-        let image = self
-            .client
-            .get(url_str)
-            .header("Range", "bytes=0-1023")
-            .send()?
-            .bytes()?;
-
-        let image_hash = HASHER.hash_one(image);
-        let suggestion = match self.hash_map.get(&image_hash) {
-            Some(name) => question
-                .suggestions
-                .iter()
-                .filter(|s| &s.value == name)
-                .next()
-                .unwrap(),
-            None => question.suggestions.first().unwrap(),
+    /// Answers the questions of `game`, showing a progress bar on a terminal
+    /// and a line per answer otherwise, so that logs stay readable. Hand
+    /// picking prints its own prompts, and gets the lines too.
+    fn play_questions(&mut self, game: &Game, summary: &mut GameSummary) -> Result<()> {
+        let progress = match !self.options.interactive && stdout().is_terminal() {
+            true => Some(progress_bar(game.nb_questions)),
+            false => None,
+        };
+        let mut questions = self.questions(game);
+        let mut i = 0;
+        while let Some(context) = questions.next() {
+            let outcome = questions.guess(context?)?;
+            i += 1;
+            summary.record(&outcome);
+            match &progress {
+                Some(bar) => {
+                    bar.set_message(format!(
+                        "score {}, {:.0}% correct",
+                        summary.total_score,
+                        summary.accuracy() * 100.
+                    ));
+                    bar.inc(1);
+                }
+                None => println!("Scored {} at question {}", outcome.score, i),
+            }
+        }
+        if let Some(bar) = progress {
+            bar.finish_and_clear();
+        }
+        Ok(())
+    }
+
+    /// Iterates over the questions of `game`, leaving how to answer them to
+    /// the caller.
+    pub fn questions<'a>(&'a mut self, game: &'a Game) -> Questions<'a> {
+        Questions {
+            player: self,
+            game,
+            asked: 0,
+            answered: 0,
+            learn: true,
+        }
+    }
+
+    /// Plays a training game with the map frozen: nothing is learned nor
+    /// recorded, and the answers to the known faces tell how often the map
+    /// is right. Unlike an evaluation, this measures the map on live faces.
+    pub fn measure_accuracy(&mut self) -> Result<AccuracyReport> {
+        if !self.options.training {
+            return Err(anyhow!("Measuring the accuracy requires a training game"));
+        }
+
+        let game = self.start_game()?;
+        let mut report = AccuracyReport::default();
+        let mut questions = self.questions(&game);
+        questions.learn = false;
+        while let Some(context) = questions.next() {
+            let context = context?;
+            let known = context.known_name().is_some();
+            let outcome = questions.guess(context)?;
+            report.record(known, outcome.is_correct);
+        }
+        if let Err(e) = self.finish_game(&game) {
+            eprintln!("Failed to retrieve the result of the game: {}", e);
+        }
+
+        Ok(report)
+    }
+
+    pub fn finish_game(&self, game: &Game) -> Result<GameResult> {
+        let url_str = FACES_ADDR.to_owned() + "/games/" + game.id.as_str();
+        let game_url = self.lucca_url()?.join(&url_str)?;
+        let response = self.send_logged(self.client.get(game_url), Accepted::Content)?;
+        let result = read_json(response)?;
+
+        Ok(result)
+    }
+
+    /// Fetches and hashes the images of `question`, and looks the face up.
+    fn context(&mut self, question: Question) -> QuestionContext {
+        // Without an image the question is still answered, so that the game
+        // goes on, but nothing can be learned from it
+        let mut undersized_image = false;
+        let image_hash = match self.image_hashes(&question) {
+            Ok(Some(hashes)) => Some(combine_hashes(hashes)),
+            Ok(None) => {
+                undersized_image = true;
+                None
+            }
+            Err(e) => {
+                eprintln!(
+                    "Failed to fetch the image of question {}: {}",
+                    question.id, e
+                );
+                None
+            }
         };
+        // Overrides come first, then the learned map, then the strategies
+        let known_name = image_hash
+            .and_then(|h| self.overrides.get(&h).or(self.hash_map.get(&h)))
+            .cloned();
+        if let Some(hash) = image_hash {
+            debug!(
+                "Question {} shows the face {}, {}",
+                question.id,
+                DisplayHash(hash),
+                known_name.as_deref().unwrap_or("unknown")
+            );
+        }
+
+        QuestionContext {
+            question,
+            image_hash,
+            undersized_image,
+            known_name,
+        }
+    }
+
+    /// The suggestion `guess` answers: the known name, the one picked by
+    /// hand in interactive mode, or the one picked by the strategies. Hand
+    /// picking is for unknown faces, or with `MaximizeScore`, for the faces
+    /// known with a low confidence.
+    fn suggest<'a>(&self, context: &'a QuestionContext) -> Result<&'a Suggestion> {
+        let suggestion = match self.fallback(context) {
+            Some(fallback) => fallback,
+            None => choose_suggestion(
+                &self.hash_map,
+                context.suggestions(),
+                context.known_name(),
+                self.options.learning_strategy,
+                self.options.unknown_strategy,
+                self.name_matcher.as_ref(),
+                self.options.key_by,
+            ),
+        };
+        let ask = match self.options.learning_strategy {
+            LearningStrategy::MaximizeScore => {
+                // Without stats, the known name is trusted
+                let confidence = context
+                    .image_hash()
+                    .and_then(|h| self.stats.confidence(h))
+                    .unwrap_or(1.);
+                context.known_name().is_some() && confidence < self.options.min_confidence
+            }
+            _ => context.is_new_face(),
+        };
+        Ok(match ask && self.options.interactive {
+            true => self
+                .label_by_hand(context, suggestion)?
+                .unwrap_or(suggestion),
+            false => suggestion,
+        })
+    }
+
+    /// The suggestion declining to answer, for a face that isn't in the map
+    /// and a question that offers one.
+    fn fallback<'a>(&self, context: &'a QuestionContext) -> Option<&'a Suggestion> {
+        let fallback = self.options.fallback_value.as_deref()?;
+        if context.known_name().is_some() {
+            return None;
+        }
+        context
+            .suggestions()
+            .iter()
+            .find(|s| self.name_matcher.matches(fallback, &s.value))
+    }
+
+    /// Answers the question of `context` with `suggestion_id` and learns the
+    /// correct suggestion.
+    ///
+    /// The next question is only requested once this returns, so the game
+    /// sees a strictly alternating sequence of `questions/next` and `guess`
+    /// calls.
+    fn answer(
+        &mut self,
+        game: &Game,
+        context: QuestionContext,
+        suggestion_id: u32,
+        learn: bool,
+    ) -> Result<GuessOutcome> {
+        let question = &context.question;
+        let image_hash = context.image_hash();
+        let suggestion = question
+            .suggestions
+            .iter()
+            .find(|s| s.id == suggestion_id)
+            .ok_or(anyhow!(
+                "Question {} has no suggestion {}",
+                question.id,
+                suggestion_id
+            ))?;
+        let new_face = context.is_new_face();
+
+        if let Some(delay) = &self.options.human_delay {
+            let delay = match delay.is_empty() {
+                true => delay.start,
+                false => self.rng.random_range(delay.clone()),
+            };
+            std::thread::sleep(delay);
+        }
+        let start = Instant::now();
+        let response = self.respond(game, question, suggestion)?;
+        self.timings.guess_submit += start.elapsed();
+        self.timings.questions += 1;
+        if let Some(image_hash) = image_hash.filter(|_| learn) {
+            let suggestions = question.suggestions.len();
+            self.stats
+                .record(image_hash, response.is_correct, suggestions);
+        }
 
-        let response = self.respond(game, &question, &suggestion)?;
         let correct_suggestion = match response.is_correct {
-            true => suggestion,
+            true => Some(suggestion),
             false => question
                 .suggestions
                 .iter()
-                .filter(|s| s.id == response.correct_suggestion_id)
-                .next()
-                .unwrap(),
+                .find(|s| s.id == response.correct_suggestion_id),
         };
+        if correct_suggestion.is_none() {
+            eprintln!(
+                "The correct answer to question {} is suggestion {}, which it doesn't offer, \
+                 nothing is learned from it",
+                question.id, response.correct_suggestion_id
+            );
+        }
+        // A suggestion without a name has nothing to be learned under, by name
+        let learned = correct_suggestion.filter(|s| {
+            learn
+                && (new_face || !self.options.skip_known)
+                && self.is_target(&s.value)
+                && !self.options.key_by.key(s).is_empty()
+        });
+
         // self.reload_hash_map()?;
-        self.hash_map
-            .insert(image_hash, correct_suggestion.value.clone());
+        if let (Some(image_hash), Some(learned)) = (image_hash, learned) {
+            let key = self.options.key_by.key(learned);
+            let votes = self.votes.entry(image_hash).or_default();
+            votes.vote(&key);
+            // Settled as when the map is loaded, so that ties don't depend
+            // on the order of the answers
+            let confirmed = votes
+                .majority()
+                .filter(|name| votes.count(name) >= self.options.learn_threshold);
+            if let Some(name) = confirmed {
+                self.hash_map.insert(image_hash, name.clone());
+            }
+        }
 
         // self.save_hash_map()?;
-        Ok(response.score)
+
+        let outcome = GuessOutcome {
+            score: response.score,
+            is_correct: response.is_correct,
+            new_face,
+            image_failure: image_hash.is_none() && !context.undersized_image,
+            undersized_image: context.undersized_image,
+            image_hash,
+            correct_name: correct_suggestion.map(|s| s.value.clone()),
+            guessed_name: suggestion.value.clone(),
+            suggestions: context.num_suggestions(),
+        };
+
+        Ok(outcome)
+    }
+
+    /// Shows the whole images of the question and asks which suggestion they
+    /// are, `best` being the one of the strategies. Failing to show them
+    /// leaves the choice to the strategies.
+    fn label_by_hand<'a>(
+        &self,
+        context: &'a QuestionContext,
+        best: &Suggestion,
+    ) -> Result<Option<&'a Suggestion>> {
+        let shown = self
+            .fetch_images(&context.question, false)
+            .and_then(|images| interactive::show_images(context.id(), &images));
+        if let Err(e) = shown {
+            eprintln!(
+                "Failed to show the image of question {}: {}",
+                context.id(),
+                e
+            );
+            return Ok(None);
+        }
+        interactive::ask_suggestion(context.suggestions(), best)
+    }
+
+    /// Hashes each image of `question`, downloading only those missing from
+    /// the URL cache. None are returned if one of them is smaller than
+    /// `min_image_bytes`.
+    fn image_hashes(&mut self, question: &Question) -> Result<Option<Vec<u64>>> {
+        let strategy = self.options.hash_strategy;
+        let mut hashes = Vec::with_capacity(question.image_urls.len());
+        for image_url in &question.image_urls {
+            if let Some((hash, size)) = self.url_cache.get(image_url, strategy) {
+                if let Some(size) = size {
+                    self.image_sizes.push(size);
+                    if self.is_undersized(question, size) {
+                        return Ok(None);
+                    }
+                }
+                hashes.push(hash);
+                continue;
+            }
+
+            let start = Instant::now();
+            let headers = match strategy {
+                HashStrategy::Headers if !image_url.starts_with(DATA_URI_PREFIX) => {
+                    self.image_headers(image_url)?
+                }
+                _ => None,
+            };
+            if let Some((hash, size)) = headers {
+                self.timings.image_fetch += start.elapsed();
+                if let Some(size) = size {
+                    self.image_sizes.push(size);
+                    if self.is_undersized(question, size) {
+                        return Ok(None);
+                    }
+                }
+                self.url_cache
+                    .insert(image_url.clone(), strategy, hash, size);
+                hashes.push(hash);
+                continue;
+            }
+            let partial = matches!(strategy, HashStrategy::Bytes | HashStrategy::Headers);
+            let (image, size) = self.fetch_image(image_url, partial)?;
+            self.image_sizes.push(size);
+            self.timings.image_fetch += start.elapsed();
+            if self.is_undersized(question, size) {
+                return Ok(None);
+            }
+            let start = Instant::now();
+            let hash = hash_image(&image, strategy);
+            self.timings.hash += start.elapsed();
+            // Inline images would only bloat the cache, they are never fetched
+            if !image_url.starts_with(DATA_URI_PREFIX) {
+                self.url_cache
+                    .insert(image_url.clone(), strategy, hash, Some(size));
+            }
+            hashes.push(hash);
+        }
+
+        Ok(Some(hashes))
+    }
+
+    fn is_undersized(&self, question: &Question, size: u64) -> bool {
+        let undersized = size < self.options.min_image_bytes;
+        if undersized {
+            eprintln!(
+                "The image of question {} is only {} bytes, a placeholder rather than a face",
+                question.id, size
+            );
+        }
+        undersized
+    }
+
+    /// Hashes the headers of the image at `image_url`, along with its size,
+    /// if they are enough to tell it apart, see `hash_headers`. A server
+    /// that doesn't answer HEAD requests gets the usual GET instead.
+    fn image_headers(&self, image_url: &str) -> Result<Option<(u64, Option<u64>)>> {
+        let url = self.lucca_url()?.join(image_url)?;
+        let mut request = self.client.head(url);
+        if let Some(timeout) = self.options.image_timeout {
+            request = request.timeout(timeout);
+        }
+        let response = match self.send_logged(request, Accepted::Any) {
+            Ok(response) => response,
+            // Some proxies drop HEAD requests that a GET gets through
+            Err(e) => {
+                debug!("HEAD request for an image failed, sending a GET: {:#}", e);
+                return Ok(None);
+            }
+        };
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        let header = |name| response.headers().get(name).and_then(|h| h.to_str().ok());
+        let length = header(CONTENT_LENGTH);
+        let hash = hash_headers(header(ETAG), header(LAST_MODIFIED), length);
+        Ok(hash.map(|hash| (hash, length.and_then(|l| l.parse().ok()))))
+    }
+
+    /// Downloads the image at `image_url`, only its first `IMAGE_BYTES`
+    /// when `partial` is set, along with the size of the whole image. Images
+    /// embedded as a data URI are decoded without any request.
+    fn fetch_image(&self, image_url: &str, partial: bool) -> Result<(Vec<u8>, u64)> {
+        if let Some(uri) = image_url.strip_prefix(DATA_URI_PREFIX) {
+            let image = decode_data_uri(uri)?;
+            let size = image.len() as u64;
+            return Ok((image, size));
+        }
+        let url_str = self.lucca_url()?.join(image_url)?;
+        let mut request = self.client.get(url_str);
+        if partial {
+            request = request.header("Range", format!("bytes=0-{}", IMAGE_BYTES - 1));
+        }
+        if let Some(timeout) = self.options.image_timeout {
+            request = request.timeout(timeout);
+        }
+        let response = self.send_logged(request, Accepted::Content)?;
+        // A partial response tells the whole size as `bytes 0-1023/5234`
+        let total_size = response
+            .headers()
+            .get("Content-Range")
+            .and_then(|r| r.to_str().ok())
+            .and_then(|r| r.rsplit_once('/'))
+            .and_then(|(_, size)| size.parse().ok());
+        let image = response.bytes()?.to_vec();
+        let size = total_size.unwrap_or(image.len() as u64);
+        Ok((image, size))
+    }
+
+    fn fetch_images(&self, question: &Question, partial: bool) -> Result<Vec<Vec<u8>>> {
+        question
+            .image_urls
+            .iter()
+            .map(|image_url| Ok(self.fetch_image(image_url, partial)?.0))
+            .collect()
+    }
+
+    fn send_logged(&self, request: RequestBuilder, accepted: Accepted) -> Result<Response> {
+        send_logged(self.transport.as_ref(), request, accepted)
+    }
+
+    /// Asks for the next question of `game`. The API only serves questions in
+    /// order, the way its web page does, and has no endpoint for a given index:
+    /// questions can't be reordered, e.g. to answer the known faces first.
+    fn next_question(&self, game: &Game) -> Result<Question> {
+        let url_str = FACES_ADDR.to_owned() + "/games/" + game.id.as_str() + "/questions/next";
+        let next_url = self.lucca_url()?.join(&url_str)?;
+        let request = self.client.post(next_url);
+        let response = send_logged(
+            self.transport.as_ref(),
+            self.options
+                .encoding
+                .apply(request, &HashMap::<String, String>::new())?,
+            Accepted::Content,
+        )?;
+        read_json(response)
     }
 
     fn respond(
@@ -241,13 +1422,890 @@ impl Player {
             + "/questions/"
             + question.id.to_string().as_str()
             + "/guess";
-        let guess_url = self.options.lucca_url.join(&url_str)?;
+        let guess_url = self.lucca_url()?.join(&url_str)?;
         let mut guess_form = HashMap::new();
         guess_form.insert("questionId", question.id);
         guess_form.insert("suggestionId", suggestion.id);
-        let response = self.client.post(guess_url).json(&guess_form).send()?;
-        let guess_response = response.json()?;
+        let request = self.client.post(guess_url);
+        let response = self.send_logged(
+            self.options.encoding.apply(request, &guess_form)?,
+            Accepted::Content,
+        )?;
+        let guess_response = read_json(response)?;
 
         Ok(guess_response)
     }
 }
+
+/// Decodes the part of a data URI after `data:`, which must be in base64, as
+/// in `image/jpeg;base64,/9j/4AAQ...`.
+fn decode_data_uri(uri: &str) -> Result<Vec<u8>> {
+    let (media_type, data) = uri
+        .split_once(',')
+        .ok_or(anyhow!("Invalid data URI, no data"))?;
+    if !media_type.ends_with(";base64") {
+        return Err(anyhow!(
+            "Unsupported data URI of type {}, not in base64",
+            media_type
+        ));
+    }
+    Ok(BASE64_STANDARD.decode(data.trim())?)
+}
+
+/// How long a rate limited response asks to wait, its `Retry-After` header
+/// being either a number of seconds or an HTTP date.
+fn retry_after(response: &Response) -> Duration {
+    let Some(value) = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return DEFAULT_RETRY_AFTER;
+    };
+    if let Ok(seconds) = value.trim().parse() {
+        return Duration::from_secs(seconds);
+    }
+    match httpdate::parse_http_date(value) {
+        Ok(date) => date
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+        Err(_) => DEFAULT_RETRY_AFTER,
+    }
+}
+
+/// Sends `request`, logging its outcome at debug level. Only the path of the
+/// URL is logged, the query could hold tokens.
+///
+/// A 429 is sent again after the wait given by its `Retry-After`, up to
+/// `RATE_LIMIT_RETRIES` times, and as long as the wait is reasonable. Any
+/// other status not `accepted` fails the request, see `rejected`.
+fn send_logged(
+    transport: &dyn Transport,
+    request: RequestBuilder,
+    accepted: Accepted,
+) -> Result<Response> {
+    let mut request = request.build()?;
+    let method = request.method().clone();
+    let path = request.url().path().to_owned();
+
+    let mut retries = 0;
+    loop {
+        // Bodies are all in memory, so requests can always be cloned
+        let retry = request.try_clone();
+
+        let start = Instant::now();
+        let response = transport.execute(request);
+        match &response {
+            Ok(response) => debug!(
+                "{} {} -> {} in {:.2?}",
+                method,
+                path,
+                response.status(),
+                start.elapsed()
+            ),
+            Err(e) => debug!("{} {} -> {} in {:.2?}", method, path, e, start.elapsed()),
+        }
+
+        let response = response?;
+        let Some(retry) = retry.filter(|_| response.status() == StatusCode::TOO_MANY_REQUESTS)
+        else {
+            return match accepted.accepts(response.status()) {
+                true => Ok(response),
+                false => Err(rejected(&method, &path, response)),
+            };
+        };
+        if retries == RATE_LIMIT_RETRIES {
+            return Err(anyhow!(
+                "Still rate limited by Lucca after {} retries",
+                retries
+            ));
+        }
+        let wait = retry_after(&response);
+        if wait > MAX_RETRY_AFTER {
+            return Err(anyhow!(
+                "Rate limited by Lucca for {} seconds, try again later",
+                wait.as_secs()
+            ));
+        }
+        eprintln!(
+            "Rate limited by Lucca, retrying in {} seconds",
+            wait.as_secs()
+        );
+        std::thread::sleep(wait);
+        retries += 1;
+        request = retry;
+    }
+}
+
+/// Elements of a login page asking for more than the password
+const CAPTCHA_SELECTOR: &str = ".g-recaptcha, .h-captcha, [data-sitekey], \
+    iframe[src*=\"recaptcha\"], iframe[src*=\"hcaptcha\"]";
+const MFA_SELECTOR: &str = "input[autocomplete=\"one-time-code\"], input[name*=\"otp\" i], \
+    input[name*=\"twofactor\" i], input[name*=\"mfa\" i]";
+
+/// The challenge a login page puts before the password is accepted, if any:
+/// without telling them apart, they would fail as a missing token.
+fn login_challenge(html: &Html) -> Option<PlayerError> {
+    let captcha = Selector::parse(CAPTCHA_SELECTOR).unwrap();
+    let mfa = Selector::parse(MFA_SELECTOR).unwrap();
+    if html.select(&captcha).next().is_some() {
+        return Some(PlayerError::CaptchaRequired);
+    }
+    if html.select(&mfa).next().is_some() {
+        return Some(PlayerError::MfaRequired);
+    }
+    None
+}
+
+/// The error for a response whose status wasn't accepted. A server error
+/// served as an HTML page is a maintenance, as with `read_json`.
+fn rejected(method: &Method, path: &str, response: Response) -> anyhow::Error {
+    let status = response.status();
+    if status.is_server_error() && is_html(&response) {
+        if let Ok(body) = response.text() {
+            let title = page_title(&body);
+            return PlayerError::Maintenance { title }.into();
+        }
+    }
+    anyhow!(
+        "{} request to {} resulted in a code {}",
+        method,
+        path,
+        status
+    )
+}
+
+fn is_html(response: &Response) -> bool {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .is_some_and(|c| c.starts_with("text/html"))
+}
+
+/// The title of an HTML page, empty if it has none.
+fn page_title(body: &str) -> String {
+    let html = Html::parse_document(body);
+    let selector = Selector::parse("title").unwrap();
+    html.select(&selector)
+        .next()
+        .map(|t| t.text().collect::<String>().trim().to_owned())
+        .unwrap_or_default()
+}
+
+/// Deserializes a JSON response, telling a maintenance page served in its
+/// place apart from an actual parsing error. Payloads wrapped in an envelope
+/// as `{"data": {...}}`, as some API versions send them, are unwrapped.
+fn read_json<T: DeserializeOwned>(response: Response) -> Result<T> {
+    let is_html = is_html(&response);
+    let body = response.text()?;
+
+    if is_html || body.trim_start().starts_with('<') {
+        let title = page_title(&body);
+        return Err(PlayerError::Maintenance { title }.into());
+    }
+
+    let mut value: serde_json::Value = serde_json::from_str(&body)?;
+    if let serde_json::Value::Object(fields) = &mut value {
+        if fields.len() == 1 && fields.get("data").is_some_and(|d| d.is_object()) {
+            value = fields.remove("data").unwrap_or_default();
+        }
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+    };
+
+    use reqwest::blocking::Request;
+
+    use super::*;
+
+    /// Paths and bodies of the requests a `Scripted` transport was sent.
+    type Sent = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// Answers with canned responses in turn, or fails as told.
+    struct Scripted {
+        responses: Mutex<VecDeque<Result<Response>>>,
+        sent: Sent,
+    }
+
+    impl Scripted {
+        fn new(responses: Vec<Response>) -> (Self, Sent) {
+            Self::with_failures(responses.into_iter().map(Ok).collect())
+        }
+
+        fn with_failures(responses: Vec<Result<Response>>) -> (Self, Sent) {
+            let sent = Sent::default();
+            let transport = Self {
+                responses: Mutex::new(responses.into()),
+                sent: sent.clone(),
+            };
+            (transport, sent)
+        }
+    }
+
+    impl Transport for Scripted {
+        fn execute(&self, request: Request) -> Result<Response> {
+            let body = request
+                .body()
+                .and_then(|b| b.as_bytes())
+                .map(|b| String::from_utf8_lossy(b).into_owned())
+                .unwrap_or_default();
+            let path = request.url().path().to_owned();
+            self.sent.lock().unwrap().push((path, body));
+            self.responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or(Err(anyhow!("No response left")))
+        }
+    }
+
+    fn response(status: u16, content_type: &str, body: &str) -> Response {
+        http::Response::builder()
+            .status(status)
+            .header("Content-Type", content_type)
+            .body(body.to_owned())
+            .unwrap()
+            .into()
+    }
+
+    const MAINTENANCE_PAGE: &str =
+        "<html><head><title> Maintenance en cours </title></head><body></body></html>";
+
+    fn maintenance_title(error: anyhow::Error) -> String {
+        match error.downcast::<PlayerError>() {
+            Ok(PlayerError::Maintenance { title }) => title,
+            other => panic!("expected a maintenance, got {:?}", other),
+        }
+    }
+
+    fn question(json: &str) -> Result<Question, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn rejects_duplicate_suggestion_ids() {
+        let error = question(
+            r#"{"id": 1, "imageUrl": "a.jpg", "suggestions": [
+                {"id": 7, "value": "Alice Martin"},
+                {"id": 8, "value": "Bruno Petit"},
+                {"id": 9, "value": "Chloé Durand"},
+                {"id": 7, "value": "Bruno Petit"}
+            ]}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("duplicate suggestion id 7"));
+    }
+
+    #[test]
+    fn accepts_distinct_suggestion_ids() {
+        let question = question(
+            r#"{"id": 1, "imageUrl": "a.jpg", "suggestions": [
+                {"id": 7, "value": "Alice Martin"},
+                {"id": 8, "value": "Bruno Petit"},
+                {"id": 9, "value": "Chloé Durand"},
+                {"id": 10, "value": "David Leroy"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(question.suggestions.len(), 4);
+    }
+
+    #[test]
+    fn reads_single_and_multiple_images() {
+        let suggestions = r#""suggestions": [
+            {"id": 7, "value": "Alice Martin"},
+            {"id": 8, "value": "Bruno Petit"},
+            {"id": 9, "value": "Chloé Durand"},
+            {"id": 10, "value": "David Leroy"}
+        ]"#;
+        let single = question(&format!(
+            r#"{{"id": 1, "imageUrl": "a.jpg", {}}}"#,
+            suggestions
+        ));
+        assert_eq!(single.unwrap().image_urls, ["a.jpg"]);
+
+        let multiple = question(&format!(
+            r#"{{"id": 1, "imageUrls": ["a.jpg", "b.jpg"], {}}}"#,
+            suggestions
+        ));
+        assert_eq!(multiple.unwrap().image_urls, ["a.jpg", "b.jpg"]);
+
+        // An image sent both ways isn't hashed twice
+        let both = question(&format!(
+            r#"{{"id": 1, "imageUrl": "b.jpg", "imageUrls": ["a.jpg", "b.jpg"], {}}}"#,
+            suggestions
+        ));
+        assert_eq!(both.unwrap().image_urls, ["a.jpg", "b.jpg"]);
+
+        let none = question(&format!(r#"{{"id": 1, {}}}"#, suggestions));
+        assert!(none.unwrap_err().to_string().contains("has no image"));
+    }
+
+    #[test]
+    fn keeps_overrides_apart_from_the_learned_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().join("data");
+        let overrides_path = dir.path().join("overrides");
+        for (path, name) in [
+            (&data_path, "Alice Martin"),
+            (&overrides_path, "Bruno Petit"),
+        ] {
+            let faces = HashMap::from([(1, Votes::new(name.to_owned()))]);
+            save_data_file(path, HashStrategy::default(), &faces, false, false).unwrap();
+        }
+
+        let mut options = PlayerOptions::new(None, true).unwrap();
+        options.data_path = data_path.clone();
+        options.overrides_path = Some(overrides_path);
+        let player = Player::new(options).unwrap();
+        assert_eq!(player.overrides[&1], "Bruno Petit");
+        assert_eq!(player.hash_map[&1], "Alice Martin");
+
+        // Overrides are never written to the learned map
+        player.save_hash_map().unwrap();
+        assert_eq!(load_hash_map(&data_path).unwrap()[&1], "Alice Martin");
+    }
+
+    #[test]
+    fn refuses_a_missing_overrides_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = PlayerOptions::new(None, true).unwrap();
+        options.data_path = dir.path().join("data");
+        options.overrides_path = Some(dir.path().join("overrides"));
+        let error = Player::new(options).err().unwrap();
+        assert!(error.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn tells_maintenance_pages_from_json() {
+        let page = response(200, "text/html; charset=utf-8", MAINTENANCE_PAGE);
+        let error = read_json::<Game>(page).unwrap_err();
+        assert_eq!(maintenance_title(error), "Maintenance en cours");
+
+        // Served without its content type
+        let page = response(200, "application/json", MAINTENANCE_PAGE);
+        let error = read_json::<Game>(page).unwrap_err();
+        assert_eq!(maintenance_title(error), "Maintenance en cours");
+
+        // Malformed JSON stays a parsing error
+        let invalid = response(200, "application/json", "{\"id\": ");
+        let error = read_json::<Game>(invalid).unwrap_err();
+        assert!(error.downcast_ref::<PlayerError>().is_none());
+    }
+
+    #[test]
+    fn reads_camel_and_snake_case_fields() {
+        for json in [
+            r#"{"id": "g1", "nbQuestions": 10}"#,
+            r#"{"id": "g1", "nb_questions": 10}"#,
+        ] {
+            let game: Game = serde_json::from_str(json).unwrap();
+            assert_eq!(game.nb_questions, 10);
+        }
+
+        let suggestions = r#""suggestions": [
+            {"id": 7, "value": "Alice Martin"},
+            {"id": 8, "value": "Bruno Petit"},
+            {"id": 9, "value": "Chloé Durand"},
+            {"id": 10, "value": "David Leroy"}
+        ]"#;
+        for field in ["imageUrl", "image_url"] {
+            let json = format!(r#"{{"id": 1, "{}": "a.jpg", {}}}"#, field, suggestions);
+            assert_eq!(question(&json).unwrap().image_urls, ["a.jpg"]);
+        }
+        for field in ["imageUrls", "image_urls"] {
+            let json = format!(r#"{{"id": 1, "{}": ["a.jpg"], {}}}"#, field, suggestions);
+            assert_eq!(question(&json).unwrap().image_urls, ["a.jpg"]);
+        }
+
+        for json in [
+            r#"{"score": 10, "isCorrect": true, "correctSuggestionId": 7}"#,
+            r#"{"score": 10, "is_correct": true, "correct_suggestion_id": 7}"#,
+        ] {
+            let response: GuessResponse = serde_json::from_str(json).unwrap();
+            assert!(response.is_correct);
+            assert_eq!(response.correct_suggestion_id, 7);
+        }
+
+        for json in [r#"{"totalScore": 120}"#, r#"{"total_score": 120}"#] {
+            let result: GameResult = serde_json::from_str(json).unwrap();
+            assert_eq!(result.total_score, 120);
+        }
+    }
+
+    fn rate_limited(retry_after: &str) -> Response {
+        http::Response::builder()
+            .status(429)
+            .header("Retry-After", retry_after)
+            .body(String::new())
+            .unwrap()
+            .into()
+    }
+
+    fn get(path: &str) -> RequestBuilder {
+        Client::new().get(format!("http://lucca.test{}", path))
+    }
+
+    #[test]
+    fn retries_rate_limited_requests() {
+        let (transport, sent) = Scripted::new(vec![
+            rate_limited("0"),
+            response(200, "application/json", "{}"),
+        ]);
+        let response = send_logged(&transport, get("/faces"), Accepted::Content).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(sent.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn gives_up_when_still_rate_limited() {
+        let responses = (0..=RATE_LIMIT_RETRIES)
+            .map(|_| rate_limited("0"))
+            .collect();
+        let (transport, sent) = Scripted::new(responses);
+        let error = send_logged(&transport, get("/faces"), Accepted::Content).unwrap_err();
+        assert!(error.to_string().contains("Still rate limited"));
+        assert_eq!(sent.lock().unwrap().len(), RATE_LIMIT_RETRIES + 1);
+
+        // Not worth waiting for
+        let (transport, sent) = Scripted::new(vec![rate_limited("3600")]);
+        let error = send_logged(&transport, get("/faces"), Accepted::Content).unwrap_err();
+        assert!(error.to_string().contains("try again later"));
+        assert_eq!(sent.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reads_retry_after_in_seconds_or_as_a_date() {
+        assert_eq!(retry_after(&rate_limited("7")), Duration::from_secs(7));
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let wait = retry_after(&rate_limited(&date));
+        assert!(wait > Duration::from_secs(57) && wait <= Duration::from_secs(60));
+        assert_eq!(
+            retry_after(&rate_limited("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Duration::ZERO
+        );
+        assert_eq!(retry_after(&rate_limited("soon")), DEFAULT_RETRY_AFTER);
+        let unspecified = response(429, "text/plain", "");
+        assert_eq!(retry_after(&unspecified), DEFAULT_RETRY_AFTER);
+    }
+
+    /// A player of canned games, starting from an empty map that isn't saved.
+    fn mock_player(training: bool, data_dir: &tempfile::TempDir) -> Player {
+        let mut options = PlayerOptions::new(None, training).unwrap();
+        options.mock = true;
+        options.no_save = true;
+        options.data_path = data_dir.path().join("data.ron");
+        Player::new(options).unwrap()
+    }
+
+    fn json(body: &str) -> Response {
+        response(200, "application/json", body)
+    }
+
+    fn image(face: &str) -> Response {
+        response(200, "image/jpeg", face)
+    }
+
+    fn question_of(image_url: &str) -> String {
+        format!(
+            r#"{{"id": 1, "imageUrl": "{}", "suggestions": [
+                {{"id": 7, "value": "Alice Martin"}},
+                {{"id": 8, "value": "Bruno Petit"}}
+            ]}}"#,
+            image_url
+        )
+    }
+
+    #[test]
+    fn learns_nothing_from_an_unknown_correct_suggestion() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut player = mock_player(true, &data_dir);
+        let (transport, _) = Scripted::new(vec![
+            json(r#"{"id": "g1", "nbQuestions": 2}"#),
+            json(&question_of("a.jpg")),
+            image("face of Alice Martin"),
+            json(r#"{"score": 0, "isCorrect": false, "correctSuggestionId": 99}"#),
+            json(&question_of("b.jpg")),
+            image("face of Bruno Petit"),
+            json(r#"{"score": 0, "isCorrect": false, "correctSuggestionId": 8}"#),
+        ]);
+        player.transport = Box::new(transport);
+
+        let game = player.start_game().unwrap();
+        let mut questions = player.questions(&game);
+        let context = questions.next().unwrap().unwrap();
+        assert!(context.image_hash().is_some());
+        let outcome = questions.answer(context, 8).unwrap();
+        assert_eq!(outcome.correct_name, None);
+
+        // The game goes on, and the next answers are learned
+        let context = questions.next().unwrap().unwrap();
+        let outcome = questions.answer(context, 7).unwrap();
+        assert_eq!(outcome.correct_name.as_deref(), Some("Bruno Petit"));
+        assert!(questions.next().is_none());
+        let names: Vec<&String> = player.hash_map.values().collect();
+        assert_eq!(names, ["Bruno Petit"]);
+    }
+
+    #[test]
+    fn checks_the_size_of_cached_images() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut player = mock_player(true, &data_dir);
+        player.options.min_image_bytes = 100;
+        // Cached under a lower minimum, and not downloaded again
+        player
+            .url_cache
+            .insert("a.jpg".to_owned(), HashStrategy::Bytes, 1, Some(20));
+        let (transport, sent) = Scripted::new(vec![
+            json(r#"{"id": "g1", "nbQuestions": 1}"#),
+            json(&question_of("a.jpg")),
+        ]);
+        player.transport = Box::new(transport);
+
+        let game = player.start_game().unwrap();
+        let context = player.questions(&game).next().unwrap().unwrap();
+        assert!(context.undersized_image);
+        assert_eq!(context.image_hash(), None);
+        assert_eq!(player.image_sizes, [20]);
+        assert_eq!(sent.lock().unwrap().len(), 2);
+    }
+
+    /// Answers of a game whose questions show the given images, along with
+    /// the id of their correct suggestion, Alice Martin being 7 and Bruno
+    /// Petit 8.
+    fn scripted_game(questions: &[(&str, u32)]) -> Vec<Response> {
+        let mut responses = vec![json(&format!(
+            r#"{{"id": "g1", "nbQuestions": {}}}"#,
+            questions.len()
+        ))];
+        for (image, id) in questions {
+            responses.push(json(&embedded_question(image)));
+            responses.push(json(&format!(
+                r#"{{"score": 0, "isCorrect": false, "correctSuggestionId": {}}}"#,
+                id
+            )));
+        }
+        responses
+    }
+
+    /// Plays the game of `responses`, answering every question with its first
+    /// suggestion.
+    fn play_scripted(player: &mut Player, responses: Vec<Response>) {
+        let (transport, _) = Scripted::new(responses);
+        player.transport = Box::new(transport);
+        let game = player.start_game().unwrap();
+        let mut questions = player.questions(&game);
+        while let Some(context) = questions.next() {
+            let context = context.unwrap();
+            let id = context.suggestions()[0].id;
+            questions.answer(context, id).unwrap();
+        }
+    }
+
+    #[test]
+    fn outvotes_a_mislabeled_face() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut player = mock_player(true, &data_dir);
+        let face = "face of Alice Martin";
+        play_scripted(
+            &mut player,
+            scripted_game(&[(face, 8), (face, 7), (face, 7)]),
+        );
+        let names: Vec<&String> = player.hash_map.values().collect();
+        assert_eq!(names, ["Alice Martin"]);
+    }
+
+    /// A question showing an embedded image, so that nothing is downloaded.
+    fn embedded_question(image: &str) -> String {
+        question_of(&format!(
+            "data:image/jpeg;base64,{}",
+            BASE64_STANDARD.encode(image)
+        ))
+    }
+
+    #[test]
+    fn settles_ties_regardless_of_the_order_of_the_answers() {
+        let face = "face of Alice Martin";
+        for answers in [[(face, 8), (face, 7)], [(face, 7), (face, 8)]] {
+            let data_dir = tempfile::tempdir().unwrap();
+            let mut player = mock_player(true, &data_dir);
+            play_scripted(&mut player, scripted_game(&answers));
+            let names: Vec<&String> = player.hash_map.values().collect();
+            assert_eq!(names, ["Alice Martin"]);
+        }
+    }
+
+    #[test]
+    fn answers_with_faces_once_confirmed() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut player = mock_player(true, &data_dir);
+        player.options.learn_threshold = 2;
+        let face = "face of Alice Martin";
+        play_scripted(&mut player, scripted_game(&[(face, 7)]));
+        assert_eq!((player.known_faces(), player.pending_faces()), (0, 1));
+        play_scripted(&mut player, scripted_game(&[(face, 7)]));
+        assert_eq!((player.known_faces(), player.pending_faces()), (1, 0));
+    }
+
+    #[test]
+    fn saves_pending_faces_only_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        for keep_pending in [false, true] {
+            let mut player = mock_player(true, &dir);
+            player.options.no_save = false;
+            player.options.data_path = dir.path().join(format!("data-{}.ron", keep_pending));
+            player.options.learn_threshold = 2;
+            player.options.keep_pending = keep_pending;
+            let (alice, bruno) = ("face of Alice Martin", "face of Bruno Petit");
+            let game = scripted_game(&[(alice, 7), (alice, 7), (bruno, 8)]);
+            play_scripted(&mut player, game);
+            assert_eq!((player.known_faces(), player.pending_faces()), (1, 1));
+            player.save_hash_map().unwrap();
+
+            let saved = DataFile::load(&player.options.data_path)
+                .unwrap()
+                .faces_for(HashStrategy::Bytes)
+                .unwrap();
+            assert_eq!(saved.len(), if keep_pending { 2 } else { 1 });
+        }
+    }
+
+    #[test]
+    fn decodes_base64_data_uris() {
+        assert_eq!(
+            decode_data_uri("image/png;base64,ZmFjZQ==").unwrap(),
+            b"face"
+        );
+        assert_eq!(decode_data_uri(";base64, ZmFjZQ==\n").unwrap(), b"face");
+        let error = decode_data_uri("image/svg+xml,<svg/>").unwrap_err();
+        assert!(error.to_string().contains("not in base64"));
+        assert!(decode_data_uri("image/png;base64").is_err());
+    }
+
+    #[test]
+    fn hashes_embedded_images_without_downloading_them() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut player = mock_player(true, &data_dir);
+        let image = "face of Alice Martin ".repeat(100);
+        let (transport, sent) = Scripted::new(vec![
+            json(r#"{"id": "g1", "nbQuestions": 1}"#),
+            json(&embedded_question(&image)),
+        ]);
+        player.transport = Box::new(transport);
+
+        let game = player.start_game().unwrap();
+        let context = player.questions(&game).next().unwrap().unwrap();
+        let expected = hash_image(&image.as_bytes()[..IMAGE_BYTES], HashStrategy::Bytes);
+        assert_eq!(context.image_hash(), Some(expected));
+        assert_eq!(sent.lock().unwrap().len(), 2);
+    }
+
+    fn send_status(status: u16, accepted: Accepted) -> Result<Response> {
+        let (transport, _) = Scripted::new(vec![response(status, "application/json", "")]);
+        send_logged(&transport, get("/faces"), accepted)
+    }
+
+    #[test]
+    fn accepts_the_statuses_each_request_expects() {
+        for accepted in [Accepted::Content, Accepted::Success, Accepted::Any] {
+            assert!(send_status(200, accepted).is_ok());
+        }
+        // Acknowledged without a body, which only matters when it is read
+        assert!(send_status(204, Accepted::Success).is_ok());
+        let error = send_status(204, Accepted::Content).unwrap_err();
+        assert!(error.to_string().contains("204"));
+
+        // Redirects the client couldn't follow
+        assert!(send_status(302, Accepted::Success).is_err());
+        let error = send_status(400, Accepted::Content).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "GET request to /faces resulted in a code 400 Bad Request"
+        );
+
+        // Left to the caller
+        for status in [302, 400, 500] {
+            let response = send_status(status, Accepted::Any).unwrap();
+            assert_eq!(response.status().as_u16(), status);
+        }
+    }
+
+    #[test]
+    fn decodes_html_entities_in_names() {
+        let question = question(
+            r#"{"id": 1, "imageUrl": "a.jpg", "suggestions": [
+                {"id": 7, "value": "Alice Martin"},
+                {"id": 8, "value": "Jo&#235;l Dupont &amp; fils"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(question.suggestions[1].value, "Joël Dupont & fils");
+
+        // Found under the name learned from a clean suggestion
+        let chosen = choose_suggestion(
+            &HashMap::new(),
+            &question.suggestions,
+            Some("Joël Dupont & fils"),
+            LearningStrategy::Score,
+            UnknownStrategy::First,
+            &NameFormat::Exact,
+            KeyBy::Name,
+        );
+        assert_eq!(chosen.id, 8);
+    }
+
+    fn context(names: &[&str], known_name: Option<&str>) -> QuestionContext {
+        let suggestions = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| Suggestion {
+                id: id as u32,
+                value: name.to_string(),
+            })
+            .collect();
+        QuestionContext {
+            question: Question {
+                id: 1,
+                image_urls: vec!["a.jpg".to_owned()],
+                suggestions,
+            },
+            image_hash: Some(1),
+            undersized_image: false,
+            known_name: known_name.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn declines_only_unknown_faces() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut player = mock_player(false, &data_dir);
+        let offered = context(&["Alice Martin", "Je ne sais pas", "Bruno Petit"], None);
+        // Not configured, a throwaway like any other
+        assert_eq!(player.suggest(&offered).unwrap().value, "Alice Martin");
+
+        player.options.fallback_value = Some("Je ne sais pas".to_owned());
+        assert_eq!(player.suggest(&offered).unwrap().value, "Je ne sais pas");
+        let known = context(
+            &["Alice Martin", "Je ne sais pas", "Bruno Petit"],
+            Some("Bruno Petit"),
+        );
+        assert_eq!(player.suggest(&known).unwrap().value, "Bruno Petit");
+        let not_offered = context(&["Alice Martin", "Bruno Petit"], None);
+        assert_eq!(player.suggest(&not_offered).unwrap().value, "Alice Martin");
+    }
+
+    #[test]
+    fn downloads_images_whose_head_request_fails() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut player = mock_player(true, &data_dir);
+        player.options.hash_strategy = HashStrategy::Headers;
+        let image = "face of Alice Martin";
+        let (transport, sent) = Scripted::with_failures(vec![
+            Ok(json(r#"{"id": "g1", "nbQuestions": 1}"#)),
+            Ok(json(
+                r#"{"id": 1, "imageUrl": "faces/1.jpg", "suggestions": [{"id": 7, "value": "Alice Martin"}]}"#,
+            )),
+            Err(anyhow!("Connection reset by peer")),
+            Ok(response(200, "image/jpeg", image)),
+        ]);
+        player.transport = Box::new(transport);
+
+        let game = player.start_game().unwrap();
+        let context = player.questions(&game).next().unwrap().unwrap();
+        let expected = hash_image(image.as_bytes(), HashStrategy::Headers);
+        assert_eq!(context.image_hash(), Some(expected));
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 4);
+        assert!(sent[2..].iter().all(|(path, _)| path == "/faces/1.jpg"));
+    }
+
+    #[test]
+    fn unwraps_payloads_sent_in_an_envelope() {
+        for body in [
+            r#"{"id": "g1", "nbQuestions": 10}"#,
+            r#"{"data": {"id": "g1", "nbQuestions": 10}}"#,
+        ] {
+            let game: Game = read_json(json(body)).unwrap();
+            assert_eq!((game.id.as_str(), game.nb_questions), ("g1", 10));
+        }
+
+        let wrapped = format!(r#"{{"data": {}}}"#, embedded_question("face"));
+        let question: Question = read_json(json(&wrapped)).unwrap();
+        assert_eq!(question.num_suggestions(), 2);
+
+        let wrapped = r#"{"data": {"score": 10, "isCorrect": true, "correctSuggestionId": 7}}"#;
+        let response: GuessResponse = read_json(json(wrapped)).unwrap();
+        assert!(response.is_correct);
+
+        // A payload that merely has a data field is kept whole
+        let result: GameResult = read_json(json(r#"{"totalScore": 120, "data": {}}"#)).unwrap();
+        assert_eq!(result.total_score, 120);
+    }
+
+    #[test]
+    fn tolerates_suggestions_without_a_name() {
+        let body = format!(
+            r#"{{"id": 1, "imageUrl": "data:image/jpeg;base64,{}", "suggestions": [
+                {{"id": 7}},
+                {{"id": 8, "value": null}},
+                {{"id": 9, "value": "Alice Martin"}}
+            ]}}"#,
+            BASE64_STANDARD.encode("face of Alice Martin")
+        );
+        let parsed = question(&body).unwrap();
+        let names: Vec<&str> = parsed
+            .suggestions
+            .iter()
+            .map(|s| s.value.as_str())
+            .collect();
+        assert_eq!(names, ["", "", "Alice Martin"]);
+
+        // Nothing to learn the face under
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut player = mock_player(true, &data_dir);
+        let (transport, _) = Scripted::new(vec![
+            json(r#"{"id": "g1", "nbQuestions": 1}"#),
+            json(&body),
+            json(r#"{"score": 0, "isCorrect": false, "correctSuggestionId": 7}"#),
+        ]);
+        player.transport = Box::new(transport);
+        let game = player.start_game().unwrap();
+        let mut questions = player.questions(&game);
+        let context = questions.next().unwrap().unwrap();
+        questions.answer(context, 9).unwrap();
+        assert!(player.votes.is_empty());
+    }
+
+    #[test]
+    fn posts_the_chosen_login_persistence() {
+        for persistent in [true, false] {
+            let data_dir = tempfile::tempdir().unwrap();
+            let mut player = mock_player(false, &data_dir);
+            player.options.persistent_login = persistent;
+            let (transport, sent) = Scripted::new(vec![
+                response(
+                    200,
+                    "text/html",
+                    r#"<input name="__RequestVerificationToken" value="tok">"#,
+                ),
+                response(200, "text/html", "<html></html>"),
+            ]);
+            player.transport = Box::new(transport);
+            player.login("alice", "secret").unwrap();
+
+            let sent = sent.lock().unwrap();
+            let (path, form) = &sent[1];
+            assert_eq!(path, "/identity/login");
+            let fields: Vec<&str> = form.split('&').collect();
+            assert!(fields.contains(&format!("IsPersistent={}", persistent).as_str()));
+            assert!(fields.contains(&"__RequestVerificationToken=tok"));
+        }
+    }
+}
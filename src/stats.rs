@@ -0,0 +1,436 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    anonymize::Anonymizer,
+    hashing::DisplayHash,
+    storage::{read_text, write_text},
+};
+
+/// Consecutive correct answers after which a face is considered learned.
+pub const LEARNED_STREAK: usize = 3;
+
+/// Number of faces listed as the hardest in the text report.
+const HARDEST: usize = 10;
+
+/// One time a face was asked, and whether it was answered correctly.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Sighting {
+    /// Seconds since the Unix epoch
+    pub at: u64,
+    pub correct: bool,
+    /// Suggestions offered, missing from the sightings of older stats files
+    #[serde(default)]
+    pub suggestions: Option<usize>,
+}
+
+/// Every sighting of each face, by hash, kept across runs.
+#[derive(Default, Debug)]
+pub struct FaceStats {
+    faces: HashMap<u64, Vec<Sighting>>,
+}
+
+impl FaceStats {
+    pub fn load(path: &Path) -> Result<Self> {
+        let faces = match path.exists() {
+            true => ron::from_str(read_text(path)?.as_str())?,
+            false => HashMap::new(),
+        };
+        Ok(Self { faces })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_text(path, &ron::to_string(&self.faces)?, false)
+    }
+
+    pub fn record(&mut self, hash: u64, correct: bool, suggestions: usize) {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.faces.entry(hash).or_default().push(Sighting {
+            at,
+            correct,
+            suggestions: Some(suggestions),
+        });
+    }
+
+    /// Share of the sightings of the face `hash` answered correctly, if it
+    /// was seen.
+    pub fn confidence(&self, hash: u64) -> Option<f64> {
+        let sightings = self.faces.get(&hash).filter(|s| !s.is_empty())?;
+        let correct = sightings.iter().filter(|s| s.correct).count();
+        Some(correct as f64 / sightings.len() as f64)
+    }
+
+    pub fn faces(&self) -> impl Iterator<Item = (u64, &[Sighting])> {
+        self.faces.iter().map(|(&h, s)| (h, s.as_slice()))
+    }
+}
+
+/// Number of sightings before the first streak of `LEARNED_STREAK` correct
+/// answers, if there was one.
+fn exposures_to_learn(sightings: &[Sighting]) -> Option<usize> {
+    let mut streak = 0;
+    for (i, sighting) in sightings.iter().enumerate() {
+        streak = match sighting.correct {
+            true => streak + 1,
+            false => 0,
+        };
+        if streak == LEARNED_STREAK {
+            return Some(i + 1 - LEARNED_STREAK);
+        }
+    }
+    None
+}
+
+/// How often a face was answered correctly.
+#[derive(Serialize, Debug)]
+pub struct FaceRecord {
+    pub hash: u64,
+    pub name: Option<String>,
+    pub seen: usize,
+    pub correct: usize,
+}
+
+impl FaceRecord {
+    pub fn anonymized(mut self, anonymizer: &Anonymizer) -> Self {
+        self.name = self.name.as_deref().map(|n| anonymizer.alias(n));
+        self
+    }
+
+    pub fn ratio(&self) -> f64 {
+        self.correct as f64 / self.seen as f64
+    }
+}
+
+/// The `n` faces seen at least `min_seen` times with the lowest share of
+/// correct answers, the most seen first among equals.
+pub fn top_missed(
+    stats: &FaceStats,
+    hash_map: &HashMap<u64, String>,
+    n: usize,
+    min_seen: usize,
+) -> Vec<FaceRecord> {
+    let mut records: Vec<FaceRecord> = stats
+        .faces()
+        .filter(|(_, sightings)| sightings.len() >= min_seen.max(1))
+        .map(|(hash, sightings)| FaceRecord {
+            hash,
+            name: hash_map.get(&hash).cloned(),
+            seen: sightings.len(),
+            correct: sightings.iter().filter(|s| s.correct).count(),
+        })
+        .collect();
+    records.sort_by(|a, b| {
+        a.ratio()
+            .total_cmp(&b.ratio())
+            .then(b.seen.cmp(&a.seen))
+            .then(a.hash.cmp(&b.hash))
+    });
+    records.truncate(n);
+    records
+}
+
+impl Display for FaceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<32} {:>5}/{:<5} {:>3.0}%  {}",
+            self.name.as_deref().unwrap_or("unknown"),
+            self.correct,
+            self.seen,
+            self.ratio() * 100.,
+            DisplayHash(self.hash)
+        )
+    }
+}
+
+/// A rough projection of a game from the past sightings, see `estimate`.
+#[derive(Serialize, Debug)]
+pub struct ScoreEstimate {
+    pub questions: u32,
+    /// Share of the past sightings showing a face the map now knows
+    pub coverage: f64,
+    /// Share of the known faces answered correctly, once learned
+    pub accuracy: f64,
+    pub expected_correct: f64,
+}
+
+/// Projects how many of `questions` questions the map would answer
+/// correctly, assuming faces show up as often as they did so far: known
+/// faces are answered as well as they were once learned, their first
+/// sighting being left out, and the others by a blind guess among as many
+/// suggestions as the questions offered on average. Stats recorded before
+/// suggestions were counted give no odds, and blind guesses count as wrong.
+///
+/// The score itself isn't projected, as it also rewards speed and no past
+/// scores are kept.
+pub fn estimate(
+    stats: &FaceStats,
+    hash_map: &HashMap<u64, String>,
+    questions: u32,
+) -> ScoreEstimate {
+    let mut sightings = 0;
+    let mut known = 0;
+    let (mut learned, mut correct) = (0, 0);
+    let (mut counted, mut blind_odds) = (0, 0.);
+    for (hash, face_sightings) in stats.faces() {
+        sightings += face_sightings.len();
+        for n in face_sightings.iter().filter_map(|s| s.suggestions) {
+            counted += 1;
+            blind_odds += 1. / n.max(1) as f64;
+        }
+        if !hash_map.contains_key(&hash) {
+            continue;
+        }
+        known += face_sightings.len();
+        let after_learning = face_sightings.get(1..).unwrap_or_default();
+        learned += after_learning.len();
+        correct += after_learning.iter().filter(|s| s.correct).count();
+    }
+
+    let ratio = |n: usize, total: usize| match total {
+        0 => 0.,
+        total => n as f64 / total as f64,
+    };
+    let coverage = ratio(known, sightings);
+    let accuracy = ratio(correct, learned);
+    let blind = match counted {
+        0 => 0.,
+        counted => blind_odds / counted as f64,
+    };
+    ScoreEstimate {
+        questions,
+        coverage,
+        accuracy,
+        expected_correct: questions as f64 * (coverage * accuracy + (1. - coverage) * blind),
+    }
+}
+
+impl Display for ScoreEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Estimate, from the past sightings: about {:.1}/{} correct answers \
+             ({:.0}% of the faces known, {:.0}% of them answered correctly)",
+            self.expected_correct,
+            self.questions,
+            self.coverage * 100.,
+            self.accuracy * 100.
+        )
+    }
+}
+
+/// A face that hasn't been learned yet despite being seen.
+#[derive(Serialize, Debug)]
+pub struct UnlearnedFace {
+    pub hash: u64,
+    pub name: Option<String>,
+    pub seen: usize,
+}
+
+/// How many exposures faces need before being answered reliably.
+#[derive(Serialize, Debug)]
+pub struct LearningCurveReport {
+    pub streak: usize,
+    /// Number of learned faces by number of exposures needed
+    pub distribution: BTreeMap<usize, usize>,
+    pub mean: f64,
+    pub median: Option<usize>,
+    /// Faces without a streak yet, the most seen first
+    pub unlearned: Vec<UnlearnedFace>,
+}
+
+impl LearningCurveReport {
+    pub fn anonymized(mut self, anonymizer: &Anonymizer) -> Self {
+        for face in &mut self.unlearned {
+            face.name = face.name.as_deref().map(|n| anonymizer.alias(n));
+        }
+        self
+    }
+}
+
+pub fn learning_curve(stats: &FaceStats, hash_map: &HashMap<u64, String>) -> LearningCurveReport {
+    let mut exposures = vec![];
+    let mut unlearned = vec![];
+    for (hash, sightings) in stats.faces() {
+        match exposures_to_learn(sightings) {
+            Some(n) => exposures.push(n),
+            None => unlearned.push(UnlearnedFace {
+                hash,
+                name: hash_map.get(&hash).cloned(),
+                seen: sightings.len(),
+            }),
+        }
+    }
+    exposures.sort_unstable();
+    unlearned.sort_by(|a, b| b.seen.cmp(&a.seen).then(a.hash.cmp(&b.hash)));
+
+    let mut distribution = BTreeMap::new();
+    for &n in &exposures {
+        *distribution.entry(n).or_default() += 1;
+    }
+    let mean = match exposures.len() {
+        0 => 0.,
+        len => exposures.iter().sum::<usize>() as f64 / len as f64,
+    };
+
+    LearningCurveReport {
+        streak: LEARNED_STREAK,
+        distribution,
+        mean,
+        median: exposures.get(exposures.len() / 2).copied(),
+        unlearned,
+    }
+}
+
+impl Display for LearningCurveReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let learned: usize = self.distribution.values().sum();
+        writeln!(
+            f,
+            "{} faces answered correctly {} times in a row, {} not yet",
+            learned,
+            self.streak,
+            self.unlearned.len()
+        )?;
+        writeln!(f, "{:>9} {:>6}", "Exposures", "Faces")?;
+        for (exposures, faces) in &self.distribution {
+            writeln!(f, "{:>9} {:>6}", exposures, faces)?;
+        }
+        write!(f, "Mean: {:.2}", self.mean)?;
+        if let Some(median) = self.median {
+            write!(f, ", median: {}", median)?;
+        }
+        for face in self.unlearned.iter().take(HARDEST) {
+            write!(
+                f,
+                "\n  {} ({}): seen {} times",
+                DisplayHash(face.hash),
+                face.name.as_deref().unwrap_or("unknown"),
+                face.seen
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sightings(answers: &[bool]) -> Vec<Sighting> {
+        answers
+            .iter()
+            .map(|&correct| Sighting {
+                at: 0,
+                correct,
+                suggestions: Some(4),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn counts_the_exposures_before_the_first_streak() {
+        let learned = sightings(&[false, true, false, true, true, true, false]);
+        assert_eq!(exposures_to_learn(&learned), Some(3));
+        assert_eq!(exposures_to_learn(&sightings(&[true; 3])), Some(0));
+        let never = sightings(&[true, true, false, true, true]);
+        assert_eq!(exposures_to_learn(&never), None);
+        assert_eq!(exposures_to_learn(&[]), None);
+    }
+
+    #[test]
+    fn reports_learned_and_unlearned_faces() {
+        let stats = FaceStats {
+            faces: HashMap::from([
+                (1, sightings(&[false, false, true, true, true])),
+                (2, sightings(&[true, false, true, true])),
+                (3, sightings(&[true, true, true])),
+            ]),
+        };
+        let hash_map = HashMap::from([(2, "Bruno Petit".to_owned())]);
+        let report = learning_curve(&stats, &hash_map);
+
+        assert_eq!(report.distribution, BTreeMap::from([(0, 1), (2, 1)]));
+        assert_eq!(report.mean, 1.);
+        assert_eq!(report.median, Some(2));
+        assert_eq!(report.unlearned.len(), 1);
+        assert_eq!(report.unlearned[0].hash, 2);
+        assert_eq!(report.unlearned[0].name.as_deref(), Some("Bruno Petit"));
+        assert_eq!(report.unlearned[0].seen, 4);
+    }
+
+    #[test]
+    fn reports_nothing_without_sightings() {
+        let report = learning_curve(&FaceStats::default(), &HashMap::new());
+        assert!(report.distribution.is_empty());
+        assert_eq!(report.mean, 0.);
+        assert_eq!(report.median, None);
+        assert!(report.unlearned.is_empty());
+    }
+
+    fn stats(faces: &[(u64, &[bool])]) -> FaceStats {
+        FaceStats {
+            faces: faces
+                .iter()
+                .map(|&(hash, answers)| (hash, sightings(answers)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn lists_the_most_seen_first_among_equally_missed_faces() {
+        let stats = stats(&[
+            (1, &[false, true]),
+            (2, &[false, false, true, true]),
+            (3, &[false, true]),
+            (4, &[false, false, false, true]),
+            (5, &[true, true, true]),
+        ]);
+        let hashes = |records: Vec<FaceRecord>| records.iter().map(|r| r.hash).collect::<Vec<_>>();
+
+        assert_eq!(
+            hashes(top_missed(&stats, &HashMap::new(), 10, 1)),
+            [4, 2, 1, 3, 5]
+        );
+        assert_eq!(hashes(top_missed(&stats, &HashMap::new(), 2, 1)), [4, 2]);
+        // Faces seen too little are left out
+        assert_eq!(
+            hashes(top_missed(&stats, &HashMap::new(), 10, 3)),
+            [4, 2, 5]
+        );
+        assert!(top_missed(&stats, &HashMap::new(), 0, 1).is_empty());
+    }
+
+    #[test]
+    fn guesses_unknown_faces_among_the_suggestions_offered() {
+        let mut stats = stats(&[(1, &[false, true, true]), (2, &[false])]);
+        let hash_map = HashMap::from([(1, "Alice Martin".to_owned())]);
+        let projected = estimate(&stats, &hash_map, 10);
+        assert_eq!((projected.coverage, projected.accuracy), (0.75, 1.));
+        assert_eq!(projected.expected_correct, 10. * (0.75 + 0.25 / 4.));
+
+        // Odds of older sightings are unknown, those of the others averaged
+        let alice = stats.faces.get_mut(&1).unwrap();
+        for (sighting, suggestions) in alice.iter_mut().zip([None, Some(2), Some(2)]) {
+            sighting.suggestions = suggestions;
+        }
+        let odds = (1. / 2. + 1. / 2. + 1. / 4.) / 3.;
+        let projected = estimate(&stats, &hash_map, 10);
+        assert!((projected.expected_correct - 10. * (0.75 + 0.25 * odds)).abs() < 1e-9);
+
+        for sightings in stats.faces.values_mut() {
+            sightings.iter_mut().for_each(|s| s.suggestions = None);
+        }
+        assert_eq!(estimate(&stats, &hash_map, 10).expected_correct, 7.5);
+    }
+}
@@ -0,0 +1,28 @@
+use std::net::UdpSocket;
+
+use anyhow::Result;
+
+use crate::summary::GameSummary;
+
+const PREFIX: &str = "lucca_faces";
+
+/// Sends the counters of a game to the statsd server at `address`, as
+/// `host:port`, in a single packet. Like every statsd client, this doesn't
+/// know whether anything was listening.
+pub fn send_metrics(address: &str, summary: &GameSummary) -> Result<()> {
+    let metrics = [
+        ("games.played", 1, "c"),
+        ("questions.answered", summary.scores.len(), "c"),
+        ("guesses.correct", summary.correct as usize, "c"),
+        ("map.size", summary.map.entries, "g"),
+    ];
+    let packet = metrics
+        .iter()
+        .map(|(name, value, kind)| format!("{}.{}:{}|{}", PREFIX, name, value, kind))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.send_to(packet.as_bytes(), address)?;
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use std::{
+    ffi::OsString,
+    fs::{copy, read, rename, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Writes `contents` next to `path` first and renames it into place, so that
+/// readers never observe a partially written file.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .and_then(|f| f.to_str())
+        .ok_or(anyhow!("Invalid file path {}", path.display()))?;
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+/// Whether a file written to `path` should be gzipped, either because it was
+/// asked for or because of its `.gz` extension.
+pub fn should_compress(path: &Path, compress: bool) -> bool {
+    compress || path.extension().is_some_and(|e| e == "gz")
+}
+
+/// Reads a text file, transparently decompressing it when it is gzipped.
+pub fn read_text(path: &Path) -> Result<String> {
+    let content = read(path)?;
+    if !content.starts_with(&GZIP_MAGIC) {
+        return Ok(String::from_utf8(content)?);
+    }
+
+    let mut text = String::new();
+    GzDecoder::new(content.as_slice()).read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Atomically writes a text file, gzipped if `compress` is set.
+pub fn write_text(path: &Path, text: &str, compress: bool) -> Result<()> {
+    if !compress {
+        return write_atomic(path, text.as_bytes());
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    write_atomic(path, &encoder.finish()?)
+}
+
+/// Path of the `n`-th most recent backup of `path`, `data.1` for `data`.
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut backup = OsString::from(path);
+    backup.push(format!(".{}", n));
+    PathBuf::from(backup)
+}
+
+/// Shifts the backups of `path` by one, dropping the oldest one, and copies
+/// `path` as the most recent one. The file itself is left in place until it
+/// is overwritten.
+pub fn rotate_backups(path: &Path, backups: usize) -> Result<()> {
+    if backups == 0 || !path.exists() {
+        return Ok(());
+    }
+
+    for n in (1..backups).rev() {
+        let backup = backup_path(path, n);
+        if backup.exists() {
+            rename(&backup, backup_path(path, n + 1))?;
+        }
+    }
+    copy(path, backup_path(path, 1))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_compressed_and_plain_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = "{1: \"Alice Martin\"}".repeat(100);
+        for compress in [false, true] {
+            let path = dir.path().join(format!("data-{}", compress));
+            write_text(&path, &text, compress).unwrap();
+            assert_eq!(read(&path).unwrap().starts_with(&GZIP_MAGIC), compress);
+            assert_eq!(read_text(&path).unwrap(), text);
+        }
+        // Only the renamed file is left, not the temporary one
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn compresses_by_extension() {
+        assert!(should_compress(Path::new("data.ron.gz"), false));
+        assert!(should_compress(Path::new("data"), true));
+        assert!(!should_compress(Path::new("data.ron"), false));
+    }
+}
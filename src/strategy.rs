@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    names::{KeyBy, NameFormat, NameMatcher},
+    player::Suggestion,
+};
+
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LearningStrategy {
+    /// Answer as well as possible
+    #[default]
+    Score,
+    /// Answer unknown faces with a throwaway suggestion, the correct one
+    /// being revealed anyway in training mode
+    MaximizeCoverage,
+    /// Like `Score`, but only spend time where it pays off, the score also
+    /// rewarding speed: faces known with enough confidence and unknown ones
+    /// are answered right away, and in interactive mode only the faces
+    /// known with a lower confidence than `min_confidence` are asked
+    MaximizeScore,
+}
+
+/// How to answer a face that isn't in the map.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnknownStrategy {
+    /// Pick the first suggestion
+    #[default]
+    First,
+    /// Pick a suggestion whose name isn't in the map yet, people already
+    /// known being likely to have another face
+    Unlearned,
+}
+
+/// Picks the suggestion matching `known_name`, the key the face is known
+/// under if any, exactly or else according to `matcher`, and otherwise
+/// falls back to the strategies.
+pub fn choose_suggestion<'a>(
+    hash_map: &HashMap<u64, String>,
+    suggestions: &'a [Suggestion],
+    known_name: Option<&str>,
+    learning: LearningStrategy,
+    unknown: UnknownStrategy,
+    matcher: &dyn NameMatcher,
+    key_by: KeyBy,
+) -> &'a Suggestion {
+    // A loose match is only trusted when it designates a single suggestion
+    let known = known_name.and_then(|key| {
+        let exact = suggestions
+            .iter()
+            .find(|s| key_by.matches(s, key, &NameFormat::Exact));
+        exact.or_else(|| {
+            let mut loose = suggestions
+                .iter()
+                .filter(|s| key_by.matches(s, key, matcher));
+            match (loose.next(), loose.next()) {
+                (Some(suggestion), None) => Some(suggestion),
+                _ => None,
+            }
+        })
+    });
+    if let Some(suggestion) = known {
+        return suggestion;
+    }
+
+    let is_learned = |s: &&Suggestion| match key_by {
+        KeyBy::Name => {
+            let value = matcher.normalize(&s.value);
+            hash_map
+                .values()
+                .any(|name| matcher.normalize(name) == value)
+        }
+        KeyBy::SuggestionId => {
+            let id = s.id.to_string();
+            hash_map.values().any(|key| *key == id)
+        }
+    };
+    let first = &suggestions[0];
+    match (learning, unknown) {
+        // A name already mapped to another face is the least likely to be
+        // this one, which makes it the ideal throwaway answer
+        (LearningStrategy::MaximizeCoverage, _) => {
+            suggestions.iter().find(is_learned).unwrap_or(first)
+        }
+        (LearningStrategy::Score | LearningStrategy::MaximizeScore, UnknownStrategy::First) => {
+            first
+        }
+        (LearningStrategy::Score | LearningStrategy::MaximizeScore, UnknownStrategy::Unlearned) => {
+            suggestions.iter().find(|s| !is_learned(s)).unwrap_or(first)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggestions(names: &[&str]) -> Vec<Suggestion> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| Suggestion {
+                id: id as u32,
+                value: name.to_string(),
+            })
+            .collect()
+    }
+
+    fn choose<'a>(
+        suggestions: &'a [Suggestion],
+        known_name: &str,
+        matcher: &dyn NameMatcher,
+    ) -> &'a str {
+        let suggestion = choose_suggestion(
+            &HashMap::new(),
+            suggestions,
+            Some(known_name),
+            LearningStrategy::Score,
+            UnknownStrategy::First,
+            matcher,
+            KeyBy::Name,
+        );
+        &suggestion.value
+    }
+
+    #[test]
+    fn answers_a_stored_name_displayed_in_another_order() {
+        let suggestions = suggestions(&["Alice Martin", "DUPONT Jean", "Bruno Petit"]);
+        assert_eq!(
+            choose(&suggestions, "Jean Dupont", &NameFormat::AnyOrder),
+            "DUPONT Jean"
+        );
+        // Not recognized, the first suggestion is a blind guess
+        assert_eq!(
+            choose(&suggestions, "Jean Dupont", &NameFormat::Exact),
+            "Alice Martin"
+        );
+    }
+
+    #[test]
+    fn prefers_exact_then_unique_loose_matches() {
+        let both = suggestions(&["Alice Martin", "Jean Pierre Martin", "Jean-Pierre Martin"]);
+        assert_eq!(
+            choose(&both, "Jean-Pierre Martin", &NameFormat::Fuzzy),
+            "Jean-Pierre Martin"
+        );
+
+        let loose = suggestions(&["Alice Martin", "Jean Pierre Martin", "Bruno Petit"]);
+        assert_eq!(
+            choose(&loose, "Jean-Pierre Martin", &NameFormat::Fuzzy),
+            "Jean Pierre Martin"
+        );
+    }
+
+    #[test]
+    fn ignores_ambiguous_loose_matches() {
+        let suggestions =
+            suggestions(&["Alice Martin", "Jean Pierre Martin", "jean-pierre martin"]);
+        assert_eq!(
+            choose(&suggestions, "Jean-Pierre Martin", &NameFormat::Fuzzy),
+            "Alice Martin"
+        );
+    }
+
+    /// Compares surnames only, for a tenant that displays nothing else.
+    struct Surname;
+
+    impl NameMatcher for Surname {
+        fn normalize(&self, name: &str) -> String {
+            name.split_whitespace()
+                .last()
+                .unwrap_or_default()
+                .to_lowercase()
+        }
+    }
+
+    #[test]
+    fn answers_with_other_matchers() {
+        let suggestions = suggestions(&["Alice Martin", "DUPONT", "Bruno Petit"]);
+        assert_eq!(choose(&suggestions, "Jean Dupont", &Surname), "DUPONT");
+    }
+}
@@ -0,0 +1,531 @@
+use std::{collections::HashSet, fmt::Display};
+
+use serde::Serialize;
+
+use crate::{
+    anonymize::Anonymizer,
+    confusion::Confusion,
+    hashing::IMAGE_BYTES,
+    player::{GameResult, GuessOutcome},
+    strategy::LearningStrategy,
+};
+
+#[derive(Serialize, Debug)]
+pub struct GameSummary {
+    pub strategy: LearningStrategy,
+    pub scores: Vec<i32>,
+    pub total_score: i32,
+    pub correct: u32,
+    pub new_faces: u32,
+    pub image_failures: u32,
+    /// Images too small to be faces, see `min_image_bytes`
+    pub undersized_images: u32,
+    /// Authoritative result from the server, when it could be retrieved
+    pub result: Option<GameResult>,
+    /// Questions answered wrongly
+    pub missed: Vec<MissedFace>,
+    /// Progress on the names being trained on, when there is a list of them
+    pub targets: Option<TargetCoverage>,
+    /// Sizes of the images downloaded, when asked for
+    pub image_sizes: Option<ImageSizes>,
+    /// Fewest and most suggestions offered by a question
+    pub suggestions: Option<(usize, usize)>,
+    /// Expected share of correct answers when guessing at random
+    pub random_baseline: f64,
+    /// Names answered for each correct name
+    pub confusion: Confusion,
+    /// Games that failed midway and were replaced by a new one
+    pub abandoned_games: usize,
+    /// Seed of the random choices, when any were made, to replay them
+    pub seed: Option<u64>,
+    /// A training game showed no new face, there is nothing left to learn
+    pub fully_known: bool,
+    /// Size of the map once the game is over
+    pub map: MapStats,
+    /// Faces learned fewer times than the learning threshold
+    pub pending_faces: usize,
+    /// Questions showing a face already shown earlier in the game, which
+    /// are answered from what the first one taught
+    pub intra_game_repeats: u32,
+    #[serde(skip)]
+    shown: HashSet<u64>,
+}
+
+/// How big the map is, to tell when it is worth pruning.
+#[derive(Serialize, Debug, Default)]
+pub struct MapStats {
+    pub entries: usize,
+    pub unique_names: usize,
+    /// Rough size in memory: 8 bytes per key plus the names, without the
+    /// overhead of the map itself
+    pub approx_bytes: usize,
+}
+
+impl Display for MapStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} faces of {} names, about {} bytes",
+            self.entries, self.unique_names, self.approx_bytes
+        )
+    }
+}
+
+/// How many people the map can name, out of the directory when its size
+/// is known.
+#[derive(Serialize, Debug)]
+pub struct CoverageReport {
+    pub learned: usize,
+    pub total: Option<usize>,
+}
+
+impl CoverageReport {
+    pub fn ratio(&self) -> Option<f64> {
+        self.total
+            .filter(|&total| total > 0)
+            .map(|total| self.learned as f64 / total as f64)
+    }
+}
+
+impl Display for CoverageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.total, self.ratio()) {
+            (Some(total), Some(ratio)) => write!(
+                f,
+                "Learned {} / ~{} faces ({:.0}%)",
+                self.learned,
+                total,
+                ratio * 100.
+            ),
+            _ => write!(f, "Learned {} names", self.learned),
+        }
+    }
+}
+
+/// Distribution of the sizes of the images, in bytes, to tune how much of
+/// them is hashed.
+#[derive(Serialize, Debug)]
+pub struct ImageSizes {
+    pub count: usize,
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+    /// Images no larger than `IMAGE_BYTES`, which are hashed whole anyway
+    pub within_range: usize,
+}
+
+impl ImageSizes {
+    pub fn new(sizes: &[u64]) -> Option<Self> {
+        let mut sizes = sizes.to_vec();
+        sizes.sort_unstable();
+        Some(Self {
+            count: sizes.len(),
+            min: *sizes.first()?,
+            median: sizes[sizes.len() / 2],
+            max: *sizes.last()?,
+            within_range: sizes.iter().filter(|&&s| s <= IMAGE_BYTES as u64).count(),
+        })
+    }
+}
+
+/// How a frozen map did on a live game, see `Player::measure_accuracy`.
+#[derive(Serialize, Debug, Default)]
+pub struct AccuracyReport {
+    pub questions: usize,
+    /// Questions showing a face of the map
+    pub known: usize,
+    /// Known faces the map named correctly
+    pub correct: usize,
+}
+
+impl AccuracyReport {
+    pub fn record(&mut self, known: bool, is_correct: bool) {
+        self.questions += 1;
+        self.known += known as usize;
+        self.correct += (known && is_correct) as usize;
+    }
+
+    /// Share of the questions showing a known face.
+    pub fn coverage(&self) -> f64 {
+        match self.questions {
+            0 => 0.,
+            n => self.known as f64 / n as f64,
+        }
+    }
+
+    /// Share of the known faces named correctly.
+    pub fn accuracy(&self) -> f64 {
+        match self.known {
+            0 => 0.,
+            n => self.correct as f64 / n as f64,
+        }
+    }
+}
+
+impl Display for AccuracyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Coverage: {}/{} ({:.0}%)",
+            self.known,
+            self.questions,
+            self.coverage() * 100.
+        )?;
+        write!(
+            f,
+            "Accuracy: {}/{} ({:.0}%)",
+            self.correct,
+            self.known,
+            self.accuracy() * 100.
+        )
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct MissedFace {
+    /// Unset when the image couldn't be fetched
+    pub hash: Option<u64>,
+    /// Unset when the server's answer matched none of the suggestions
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TargetCoverage {
+    pub targets: usize,
+    pub learned: usize,
+    pub missing: Vec<String>,
+}
+
+impl GameSummary {
+    pub fn new(strategy: LearningStrategy) -> Self {
+        Self {
+            strategy,
+            scores: vec![],
+            total_score: 0,
+            correct: 0,
+            new_faces: 0,
+            image_failures: 0,
+            undersized_images: 0,
+            result: None,
+            missed: vec![],
+            targets: None,
+            image_sizes: None,
+            suggestions: None,
+            random_baseline: 0.,
+            confusion: Confusion::default(),
+            abandoned_games: 0,
+            seed: None,
+            fully_known: false,
+            map: MapStats::default(),
+            pending_faces: 0,
+            intra_game_repeats: 0,
+            shown: HashSet::new(),
+        }
+    }
+
+    /// The summary with every name replaced by its alias.
+    pub fn anonymized(mut self, anonymizer: &Anonymizer) -> Self {
+        for missed in &mut self.missed {
+            missed.name = missed.name.as_deref().map(|n| anonymizer.alias(n));
+        }
+        if let Some(targets) = &mut self.targets {
+            for name in &mut targets.missing {
+                *name = anonymizer.alias(name);
+            }
+        }
+        self.confusion = self.confusion.anonymized(anonymizer);
+        self
+    }
+
+    /// Share of the questions that showed a face unknown until then.
+    pub fn new_face_ratio(&self) -> f64 {
+        match self.scores.len() {
+            0 => 0.,
+            n => self.new_faces as f64 / n as f64,
+        }
+    }
+
+    pub fn average_score(&self) -> f64 {
+        match self.scores.len() {
+            0 => 0.,
+            n => self.total_score as f64 / n as f64,
+        }
+    }
+
+    /// Share of the questions answered correctly.
+    pub fn accuracy(&self) -> f64 {
+        match self.scores.len() {
+            0 => 0.,
+            n => self.correct as f64 / n as f64,
+        }
+    }
+
+    /// The score of the game, as the server counted it when it told.
+    pub fn score(&self) -> i32 {
+        self.result
+            .as_ref()
+            .map_or(self.total_score, |r| r.total_score)
+    }
+
+    pub fn record(&mut self, outcome: &GuessOutcome) {
+        self.scores.push(outcome.score);
+        self.total_score += outcome.score;
+        self.correct += outcome.is_correct as u32;
+        self.new_faces += outcome.new_face as u32;
+        self.image_failures += outcome.image_failure as u32;
+        self.undersized_images += outcome.undersized_image as u32;
+        if let Some(hash) = outcome.image_hash {
+            self.intra_game_repeats += !self.shown.insert(hash) as u32;
+        }
+        let n = outcome.suggestions;
+        self.suggestions = Some(match self.suggestions {
+            Some((min, max)) => (min.min(n), max.max(n)),
+            None => (n, n),
+        });
+        // Running mean of 1/n, as the number of suggestions may vary
+        self.random_baseline += (1. / n as f64 - self.random_baseline) / self.scores.len() as f64;
+        if let Some(correct_name) = &outcome.correct_name {
+            self.confusion.record(correct_name, &outcome.guessed_name);
+        }
+        if !outcome.is_correct {
+            self.missed.push(MissedFace {
+                hash: outcome.image_hash,
+                name: outcome.correct_name.clone(),
+            });
+        }
+    }
+}
+
+impl Display for GameSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.image_failures > 0 {
+            writeln!(f, "Images that failed to download: {}", self.image_failures)?;
+        }
+        if self.undersized_images > 0 {
+            writeln!(
+                f,
+                "Images too small to be faces: {}",
+                self.undersized_images
+            )?;
+        }
+        if self.abandoned_games > 0 {
+            writeln!(
+                f,
+                "Games abandoned after a failure: {}, their answers are counted",
+                self.abandoned_games
+            )?;
+        }
+        match self.strategy {
+            LearningStrategy::Score | LearningStrategy::MaximizeScore => match &self.result {
+                Some(result) if result.total_score != self.total_score => write!(
+                    f,
+                    "Total score: {} (sum of the answers: {})",
+                    result.total_score, self.total_score
+                )?,
+                Some(result) => write!(f, "Total score: {}", result.total_score)?,
+                None => write!(f, "Total score: {}", self.total_score)?,
+            },
+            LearningStrategy::MaximizeCoverage => write!(
+                f,
+                "New faces learned: {}/{} ({:.0}%)",
+                self.new_faces,
+                self.scores.len(),
+                self.new_face_ratio() * 100.
+            )?,
+        }
+        if self.strategy == LearningStrategy::MaximizeScore {
+            write!(
+                f,
+                "\nAverage score per question: {:.1}",
+                self.average_score()
+            )?;
+        }
+        if let Some(rank) = self.result.as_ref().and_then(|r| r.rank) {
+            write!(f, "\nRank: {}", rank)?;
+        }
+        if let Some(percentile) = self.result.as_ref().and_then(|r| r.percentile) {
+            write!(f, "\nPercentile: {}", percentile)?;
+        }
+        if let Some(targets) = &self.targets {
+            write!(f, "\n{}", targets)?;
+        }
+        if let Some((min, max)) = self.suggestions {
+            let range = match min == max {
+                true => min.to_string(),
+                false => format!("{} to {}", min, max),
+            };
+            write!(
+                f,
+                "\nSuggestions per question: {}, {:.0}% correct guessing at random",
+                range,
+                self.random_baseline * 100.
+            )?;
+        }
+        if let Some(sizes) = &self.image_sizes {
+            write!(
+                f,
+                "\nImage sizes: {} min, {} median, {} max bytes, {}/{} within the first {} bytes",
+                sizes.min, sizes.median, sizes.max, sizes.within_range, sizes.count, IMAGE_BYTES
+            )?;
+        }
+        if self.intra_game_repeats > 0 {
+            write!(
+                f,
+                "\nFaces shown again within the game: {}",
+                self.intra_game_repeats
+            )?;
+        }
+        if self.pending_faces > 0 {
+            write!(f, "\nFaces awaiting confirmation: {}", self.pending_faces)?;
+        }
+        if self.fully_known {
+            write!(f, "\nNo new face in this game, training is complete")?;
+        }
+        if let Some(seed) = self.seed {
+            write!(f, "\nRandom seed: {}, replay with --seed {}", seed, seed)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for TargetCoverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ratio = match self.targets {
+            0 => 0.,
+            n => self.learned as f64 / n as f64,
+        };
+        write!(
+            f,
+            "Targets learned: {}/{} ({:.0}%)",
+            self.learned,
+            self.targets,
+            ratio * 100.
+        )?;
+        if !self.missing.is_empty() {
+            write!(f, "\nMissing: {}", self.missing.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The games of a `--games` run, leaving out the warmup ones which only
+/// learn.
+#[derive(Serialize, Debug, Default)]
+pub struct RunSummary {
+    pub warmup_games: u32,
+    /// Score of each measured game, in order
+    pub scores: Vec<i32>,
+    /// Measured game with the highest score, from 1, the first among equals
+    pub best_game: Option<usize>,
+    pub questions: usize,
+    pub correct: u32,
+}
+
+impl RunSummary {
+    pub fn new(warmup_games: u32) -> Self {
+        Self {
+            warmup_games,
+            ..Self::default()
+        }
+    }
+
+    pub fn record(&mut self, summary: &GameSummary) {
+        let score = summary.score();
+        if self.best_score().is_none_or(|best| score > best) {
+            self.best_game = Some(self.scores.len() + 1);
+        }
+        self.scores.push(score);
+        self.questions += summary.scores.len();
+        self.correct += summary.correct;
+    }
+
+    pub fn best_score(&self) -> Option<i32> {
+        self.best_game.map(|game| self.scores[game - 1])
+    }
+
+    pub fn average_score(&self) -> f64 {
+        match self.scores.len() {
+            0 => 0.,
+            n => self.scores.iter().sum::<i32>() as f64 / n as f64,
+        }
+    }
+
+    /// Share of the questions of the measured games answered correctly.
+    pub fn accuracy(&self) -> f64 {
+        match self.questions {
+            0 => 0.,
+            n => self.correct as f64 / n as f64,
+        }
+    }
+}
+
+impl Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Games: {}", self.scores.len())?;
+        if self.warmup_games > 0 {
+            write!(f, ", leaving out {} warmup", self.warmup_games)?;
+        }
+        if let (Some(game), Some(score)) = (self.best_game, self.best_score()) {
+            write!(f, "\nBest score: {}, at game {}", score, game)?;
+        }
+        write!(f, "\nAverage score: {:.1}", self.average_score())?;
+        write!(
+            f,
+            "\nCorrect answers: {}/{} ({:.0}%)",
+            self.correct,
+            self.questions,
+            self.accuracy() * 100.
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A game whose answers scored `scores`, the positive ones being correct.
+    fn game(scores: &[i32]) -> GameSummary {
+        let mut summary = GameSummary::new(LearningStrategy::Score);
+        for &score in scores {
+            summary.record(&GuessOutcome {
+                score,
+                is_correct: score > 0,
+                new_face: false,
+                image_failure: false,
+                undersized_image: false,
+                image_hash: None,
+                correct_name: None,
+                guessed_name: "Alice Martin".to_owned(),
+                suggestions: 4,
+            });
+        }
+        summary
+    }
+
+    #[test]
+    fn sums_up_the_measured_games() {
+        let mut run = RunSummary::new(2);
+        run.record(&game(&[10, 0, 10]));
+        let mut reported = game(&[10, 10, 10]);
+        reported.result = Some(GameResult {
+            total_score: 35,
+            rank: None,
+            percentile: None,
+        });
+        run.record(&reported);
+
+        run.record(&game(&[10, 10, 15]));
+
+        // The server's score wins over the sum of the answers
+        assert_eq!(run.scores, [20, 35, 35]);
+        assert_eq!(run.best_game, Some(2));
+        assert_eq!(run.average_score(), 30.);
+        assert_eq!((run.correct, run.questions), (8, 9));
+        assert_eq!(
+            run.to_string(),
+            "Games: 3, leaving out 2 warmup\nBest score: 35, at game 2\nAverage score: 30.0\n\
+             Correct answers: 8/9 (89%)"
+        );
+        assert_eq!(RunSummary::new(0).best_score(), None);
+    }
+}
@@ -0,0 +1,56 @@
+use std::{fmt::Display, time::Duration};
+
+/// Time spent in each phase of a run, accumulated over all questions.
+#[derive(Debug, Default)]
+pub struct Timings {
+    pub login: Duration,
+    pub next_question: Duration,
+    pub image_fetch: Duration,
+    pub hash: Duration,
+    pub guess_submit: Duration,
+    pub questions: u32,
+}
+
+/// Questions answered per second over whole games, see `--bench`.
+#[derive(Debug)]
+pub struct Bench {
+    pub games: u32,
+    pub questions: u32,
+    pub elapsed: Duration,
+}
+
+impl Display for Bench {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} questions over {} games in {:.2?}: {:.0} questions per second",
+            self.questions,
+            self.games,
+            self.elapsed,
+            self.questions as f64 / self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let per_question = |total: Duration| total / self.questions.max(1);
+        writeln!(f, "{:<16} {:>12} {:>14}", "Phase", "Total", "Per question")?;
+        writeln!(f, "{:<16} {:>12.2?}", "login", self.login)?;
+        for (phase, total) in [
+            ("next question", self.next_question),
+            ("image fetch", self.image_fetch),
+            ("hash", self.hash),
+            ("guess submit", self.guess_submit),
+        ] {
+            writeln!(
+                f,
+                "{:<16} {:>12.2?} {:>14.2?}",
+                phase,
+                total,
+                per_question(total)
+            )?;
+        }
+        write!(f, "{} questions", self.questions)
+    }
+}
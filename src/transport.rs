@@ -0,0 +1,361 @@
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use log::{log_enabled, trace, Level};
+use reqwest::{
+    blocking::{Client, Request, Response},
+    Method,
+};
+use serde_json::{json, Value};
+
+/// Sends the requests built by `Player`, over HTTP or to a simulated server.
+pub trait Transport: Send + Sync {
+    fn execute(&self, request: Request) -> Result<Response>;
+}
+
+pub struct HttpTransport(pub Client);
+
+impl Transport for HttpTransport {
+    fn execute(&self, request: Request) -> Result<Response> {
+        Ok(self.0.execute(request)?)
+    }
+}
+
+/// Fields never traced, matched case-insensitively anywhere in their name.
+const SECRET_FIELDS: [&str; 2] = ["password", "token"];
+
+/// Logs the bodies sent and received through another transport, at trace
+/// level. JSON and form bodies are logged with their secret fields
+/// redacted, the others only by their size.
+pub struct TracingTransport(pub Box<dyn Transport>);
+
+fn is_secret(field: &str) -> bool {
+    let field = field.to_lowercase();
+    SECRET_FIELDS.iter().any(|secret| field.contains(secret))
+}
+
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (field, value) in fields {
+                match is_secret(field) {
+                    true => *value = Value::from("<redacted>"),
+                    false => redact_json(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+/// The body as it can be logged, `content_type` telling whether it is
+/// text. Bodies that aren't JSON are redacted as form-urlencoded ones, which
+/// are the only others sent.
+fn traced_body(body: &[u8], content_type: Option<&str>) -> String {
+    let is_text = content_type.is_none_or(|c| {
+        c.starts_with("application/json") || c.starts_with("application/x-www-form-urlencoded")
+    });
+    let text = std::str::from_utf8(body).ok().filter(|_| is_text);
+    let Some(text) = text else {
+        return format!(
+            "<{} bytes of {}>",
+            body.len(),
+            content_type.unwrap_or("data")
+        );
+    };
+    if let Ok(mut value) = serde_json::from_str::<Value>(text) {
+        redact_json(&mut value);
+        return value.to_string();
+    }
+    text.split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((field, _)) if is_secret(field) => format!("{}=<redacted>", field),
+            _ => pair.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn content_type(headers: &http::HeaderMap) -> Option<&str> {
+    headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+}
+
+impl Transport for TracingTransport {
+    fn execute(&self, request: Request) -> Result<Response> {
+        // Responses are only buffered when their bodies are actually traced
+        if !log_enabled!(Level::Trace) {
+            return self.0.execute(request);
+        }
+
+        let method = request.method().clone();
+        let path = request.url().path().to_owned();
+        if let Some(body) = request.body().and_then(|b| b.as_bytes()) {
+            let body = traced_body(body, content_type(request.headers()));
+            trace!("{} {} <- {}", method, path, body);
+        }
+
+        let response = self.0.execute(request)?;
+        // The body can only be read once, the response is rebuilt around it
+        let (status, version) = (response.status(), response.version());
+        let headers = response.headers().clone();
+        let body = response.bytes()?.to_vec();
+        trace!(
+            "{} {} -> {}",
+            method,
+            path,
+            traced_body(&body, content_type(&headers))
+        );
+
+        let mut rebuilt = http::Response::new(body);
+        *rebuilt.status_mut() = status;
+        *rebuilt.version_mut() = version;
+        *rebuilt.headers_mut() = headers;
+        Ok(rebuilt.into())
+    }
+}
+
+/// Base URL the mock transport answers to.
+pub const MOCK_URL: &str = "http://lucca.mock/";
+
+const MOCK_QUESTIONS: u32 = 10;
+const MOCK_PEOPLE: [&str; 8] = [
+    "Alice Martin",
+    "Bruno Petit",
+    "Chloé Durand",
+    "David Leroy",
+    "Emma Moreau",
+    "Farid Benali",
+    "Gaëlle Simon",
+    "Hugo Laurent",
+];
+
+#[derive(Default)]
+struct MockGame {
+    seed: u64,
+    question: u32,
+    /// Index in `MOCK_PEOPLE` of the person shown by the current question
+    answer: usize,
+    total_score: i32,
+}
+
+/// Serves canned games, with faces drawn from `MOCK_PEOPLE`, so that the
+/// whole flow runs without a Lucca instance.
+#[derive(Default)]
+pub struct MockTransport {
+    game: Mutex<MockGame>,
+}
+
+/// The image of a person, the same bytes every time it is asked.
+fn mock_image(person: usize) -> Vec<u8> {
+    format!("mock face of {}\n", MOCK_PEOPLE[person])
+        .repeat(64)
+        .into_bytes()
+}
+
+/// Reads a field of a JSON or form-urlencoded body.
+fn body_field(request: &Request, name: &str) -> Option<String> {
+    let body = std::str::from_utf8(request.body()?.as_bytes()?).ok()?;
+    if let Ok(Value::Object(fields)) = serde_json::from_str(body) {
+        return fields.get(name).map(|v| v.to_string());
+    }
+    body.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_owned())
+}
+
+fn response(status: u16, content_type: &str, body: Vec<u8>) -> Result<Response> {
+    let response = http::Response::builder()
+        .status(status)
+        .header("Content-Type", content_type)
+        .body(body)?;
+    Ok(response.into())
+}
+
+fn json_response(body: Value) -> Result<Response> {
+    response(200, "application/json", body.to_string().into_bytes())
+}
+
+impl MockGame {
+    /// A small linear congruential generator, so that runs are reproducible.
+    fn next_random(&mut self) -> usize {
+        self.seed = self
+            .seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.seed >> 33) as usize
+    }
+
+    fn next_question(&mut self) -> Value {
+        self.question += 1;
+        let mut people: Vec<usize> = (0..MOCK_PEOPLE.len()).collect();
+        let mut suggestions = vec![];
+        for _ in 0..4 {
+            let i = self.next_random() % people.len();
+            suggestions.push(people.swap_remove(i));
+        }
+        self.answer = suggestions[self.next_random() % suggestions.len()];
+
+        json!({
+            "id": self.question,
+            "imageUrl": format!("mock/faces/{}.jpg", self.answer),
+            "suggestions": suggestions
+                .iter()
+                .map(|&p| json!({"id": p, "value": MOCK_PEOPLE[p]}))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl Transport for MockTransport {
+    fn execute(&self, request: Request) -> Result<Response> {
+        let mut game = self
+            .game
+            .lock()
+            .map_err(|_| anyhow!("The mock game was poisoned"))?;
+        let path = request.url().path().trim_start_matches('/').to_owned();
+        let method = request.method().clone();
+
+        if path == "identity/login" {
+            return match method {
+                Method::GET => response(
+                    200,
+                    "text/html",
+                    b"<input name=\"__RequestVerificationToken\" value=\"mock\">".to_vec(),
+                ),
+                _ => response(200, "text/html", vec![]),
+            };
+        }
+        if path == "identity/logout" {
+            return response(200, "text/html", vec![]);
+        }
+        if let Some(person) = path
+            .strip_prefix("mock/faces/")
+            .and_then(|p| p.strip_suffix(".jpg"))
+        {
+            let person: usize = person.parse()?;
+            let image = mock_image(person);
+            let response = http::Response::builder()
+                .header("Content-Type", "image/jpeg")
+                .header("Content-Length", image.len())
+                .header("ETag", format!("\"mock-{}\"", person));
+            // Answered with headers only, as HEAD requests are
+            let body = match method {
+                Method::HEAD => vec![],
+                _ => image,
+            };
+            return Ok(response.body(body)?.into());
+        }
+
+        let is_new_game = matches!(
+            path.as_str(),
+            "faces/api/games" | "faces/api/games/training"
+        );
+        if method == Method::POST && is_new_game {
+            *game = MockGame {
+                seed: game.seed,
+                ..MockGame::default()
+            };
+            return json_response(json!({"id": "mock", "nbQuestions": MOCK_QUESTIONS}));
+        }
+        if path == "faces/api/games/mock/questions/next" {
+            return json_response(game.next_question());
+        }
+        if path.starts_with("faces/api/games/mock/questions/") && path.ends_with("/guess") {
+            let answer = game.answer;
+            let is_correct = body_field(&request, "suggestionId") == Some(answer.to_string());
+            let score = if is_correct { 100 } else { 0 };
+            game.total_score += score;
+            return json_response(json!({
+                "score": score,
+                "isCorrect": is_correct,
+                "correctSuggestionId": answer,
+            }));
+        }
+        if path == "faces/api/games/mock" {
+            return json_response(json!({"totalScore": game.total_score}));
+        }
+
+        response(404, "text/plain", b"Not found".to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(transport: &MockTransport, path: &str) -> Value {
+        let request = Client::new()
+            .post(format!("{}{}", MOCK_URL, path))
+            .build()
+            .unwrap();
+        transport.execute(request).unwrap().json().unwrap()
+    }
+
+    /// The questions of a mock training game.
+    fn mock_questions() -> Vec<Value> {
+        let transport = MockTransport::default();
+        let game = post(&transport, "faces/api/games/training");
+        (0..game["nbQuestions"].as_u64().unwrap())
+            .map(|_| post(&transport, "faces/api/games/mock/questions/next"))
+            .collect()
+    }
+
+    #[test]
+    fn serves_the_same_games_every_run() {
+        let questions = mock_questions();
+        assert_eq!(questions.len(), MOCK_QUESTIONS as usize);
+        assert_eq!(questions, mock_questions());
+        // Not the same question over and over
+        assert!(questions
+            .iter()
+            .any(|q| q["imageUrl"] != questions[0]["imageUrl"]));
+    }
+
+    #[test]
+    fn redacts_secret_json_fields_at_any_depth() {
+        let body = json!({
+            "user": {"name": "Alice Martin", "Password": "hunter2"},
+            "sessions": [{"accessToken": "abc123", "id": 1}],
+        });
+        let traced = traced_body(body.to_string().as_bytes(), Some("application/json"));
+        assert!(!traced.contains("hunter2") && !traced.contains("abc123"));
+        let traced: Value = serde_json::from_str(&traced).unwrap();
+        assert_eq!(traced["user"]["Password"], "<redacted>");
+        assert_eq!(traced["user"]["name"], "Alice Martin");
+        assert_eq!(traced["sessions"][0]["accessToken"], "<redacted>");
+        assert_eq!(traced["sessions"][0]["id"], 1);
+    }
+
+    #[test]
+    fn never_traces_the_password_of_the_login_form() {
+        let form = [
+            ("UserName", "alice"),
+            ("Password", "p&ss=w0rd secret"),
+            ("__RequestVerificationToken", "tok123"),
+        ];
+        let request = Client::new()
+            .post(format!("{}identity/login", MOCK_URL))
+            .form(&form)
+            .build()
+            .unwrap();
+        let body = request.body().unwrap().as_bytes().unwrap();
+        let traced = traced_body(body, content_type(request.headers()));
+        assert_eq!(
+            traced,
+            "UserName=alice&Password=<redacted>&__RequestVerificationToken=<redacted>"
+        );
+    }
+
+    #[test]
+    fn traces_other_bodies_by_their_size() {
+        assert_eq!(
+            traced_body(b"\xff\xd8\xff", Some("image/jpeg")),
+            "<3 bytes of image/jpeg>"
+        );
+        assert_eq!(traced_body(b"\xff\xd8", None), "<2 bytes of data>");
+    }
+}
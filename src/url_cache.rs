@@ -0,0 +1,87 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hashing::HashStrategy,
+    storage::{read_text, write_text},
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CachedHash {
+    hash: u64,
+    strategy: HashStrategy,
+    /// Bytes of the image, when known, so that hits still tell placeholders
+    /// apart
+    #[serde(default)]
+    size: Option<u64>,
+    /// Seconds since the Unix epoch
+    cached_at: u64,
+}
+
+/// Hashes of the images already downloaded, by URL, so that a face seen in a
+/// previous run is answered without downloading it again. Entries expire as
+/// URLs may be reused for other images.
+#[derive(Debug)]
+pub struct UrlCache {
+    entries: HashMap<String, CachedHash>,
+    ttl: Duration,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl UrlCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            ttl,
+        }
+    }
+
+    /// Reads the cache at `path`, if any, leaving out the expired entries.
+    pub fn load(path: &Path, ttl: Duration) -> Result<Self> {
+        let mut entries: HashMap<String, CachedHash> = match path.exists() {
+            true => ron::from_str(read_text(path)?.as_str())?,
+            false => HashMap::new(),
+        };
+        let now = now();
+        entries.retain(|_, entry| now.saturating_sub(entry.cached_at) < ttl.as_secs());
+
+        Ok(Self { entries, ttl })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_text(path, &ron::to_string(&self.entries)?, false)
+    }
+
+    /// The hash of the image at `url` and its size, if the hash was computed
+    /// with `strategy` and hasn't expired.
+    pub fn get(&self, url: &str, strategy: HashStrategy) -> Option<(u64, Option<u64>)> {
+        self.entries
+            .get(url)
+            .filter(|e| {
+                e.strategy == strategy && now().saturating_sub(e.cached_at) < self.ttl.as_secs()
+            })
+            .map(|e| (e.hash, e.size))
+    }
+
+    pub fn insert(&mut self, url: String, strategy: HashStrategy, hash: u64, size: Option<u64>) {
+        let entry = CachedHash {
+            hash,
+            strategy,
+            size,
+            cached_at: now(),
+        };
+        self.entries.insert(url, entry);
+    }
+}